@@ -0,0 +1,39 @@
+//! Overhead of the lock-free count-only histogram versus the mutex-wrapped full histogram,
+//! `cargo bench --bench count_only`.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use shim::{Config, CountOnlyHistogram, Histogram};
+
+const THREADS: u64 = 8;
+const SAMPLES: u64 = 1_000_000;
+
+fn run(append: impl Fn(u64) + Send + Sync + 'static) -> Duration {
+    let append = Arc::new(append);
+    let started = Instant::now();
+    let threads: Vec<_> = (0..THREADS).map(|_| {
+        let append = append.clone();
+        thread::spawn(move || for x in 0..SAMPLES { append(x) })
+    }).collect();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    started.elapsed()
+}
+
+fn main() {
+    let per_sample = |d: Duration| d.as_nanos() as f64 / (THREADS * SAMPLES) as f64;
+
+    let count_only = Arc::new(CountOnlyHistogram::new(&Config::default()));
+    let h = count_only.clone();
+    let lock_free = run(move |x| h.append(x));
+    assert_eq!(count_only.count(), THREADS * SAMPLES);
+
+    let full = Arc::new(Mutex::new(Histogram::new(Config::default())));
+    let h = full.clone();
+    let locked = run(move |x| h.lock().unwrap().append(x));
+    assert_eq!(full.lock().unwrap().sample_count(), THREADS * SAMPLES);
+
+    println!("count only  {:>8.1} ns/sample", per_sample(lock_free));
+    println!("mutex full  {:>8.1} ns/sample", per_sample(locked));
+}
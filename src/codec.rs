@@ -0,0 +1,512 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Bucket, Config, Histogram, HistogramError, PercentileEstimator, PercentileWindow, Range, Rounding, Scale, Tail, TimeUnit};
+
+/// binary layout version
+const VERSION: u8 = 2;
+
+impl Histogram {
+    /// append little-endian binary dump:
+    /// version, config, elapsed seconds, ranges, then buckets of time, scales and range
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(VERSION);
+        out.extend_from_slice(&(self.config.percentiles.len() as u32).to_le_bytes());
+        for p in &self.config.percentiles {
+            out.extend_from_slice(&p.to_le_bytes());
+        }
+        out.push(self.config.span_sec);
+        out.extend_from_slice(&self.config.live_time_sec.to_le_bytes());
+        out.push(match self.config.time_unit {
+            TimeUnit::Nanos => 0,
+            TimeUnit::Micros => 1,
+            TimeUnit::Millis => 2,
+        });
+        out.extend_from_slice(&(self.config.top_n.map_or(0, |n| n + 1) as u32).to_le_bytes());
+        out.extend_from_slice(&(self.config.min_samples_for_percentile as u32).to_le_bytes());
+        out.push(match self.config.rounding {
+            Rounding::RoundDown => 0,
+            Rounding::RoundNearest => 1,
+        });
+        out.push(self.config.tail_retention.unwrap_or(0));
+        out.push(match self.config.tail {
+            Tail::Both => 0,
+            Tail::Upper => 1,
+            Tail::Lower => 2,
+        });
+        match self.config.max_valid_value {
+            Some(max) => {
+                out.push(1 + self.config.clamp_invalid as u8);
+                out.extend_from_slice(&max.to_le_bytes());
+            }
+            None => out.push(0),
+        }
+        out.push(match self.config.estimator {
+            PercentileEstimator::RangeBand => 0,
+            PercentileEstimator::WeightedBuckets => 1,
+            #[cfg(feature = "tdigest")]
+            PercentileEstimator::TDigest => 2,
+        });
+        out.extend_from_slice(&self.config.warmup_sec.to_le_bytes());
+        out.push(self.config.log_scale as u8);
+        out.push(match self.config.window {
+            PercentileWindow::Live => 0,
+            PercentileWindow::Lifetime => 1,
+        });
+        out.extend_from_slice(&(self.config.extreme_log_size as u32).to_le_bytes());
+        out.extend_from_slice(&self.config.min_range_width.to_le_bytes());
+        out.extend_from_slice(&self.config.relative_error.to_le_bytes());
+        out.push(self.config.lifetime_only as u8);
+        out.extend_from_slice(&self.config.skew.to_bits().to_le_bytes());
+        out.extend_from_slice(&self.now().to_le_bytes());
+        encode_range(&self.range, out);
+        encode_range(&self.range_lifetime, out);
+        out.extend_from_slice(&self.rejected.to_le_bytes());
+        out.extend_from_slice(&(self.lifetime.len() as u32).to_le_bytes());
+        for s in &self.lifetime {
+            encode_scale(s, out);
+        }
+        if self.config.extreme_log_size > 0 {
+            out.extend_from_slice(&(self.extremes.len() as u32).to_le_bytes());
+            for (time, value) in &self.extremes {
+                out.extend_from_slice(&time.to_le_bytes());
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&(self.buckets.len() as u32).to_le_bytes());
+        for b in &self.buckets {
+            out.extend_from_slice(&b.time.to_le_bytes());
+            out.extend_from_slice(&(b.scale.len() as u32).to_le_bytes());
+            for s in &b.scale {
+                encode_scale(s, out);
+            }
+            encode_range(&b.range, out);
+            out.extend_from_slice(&(b.top.len() as u32).to_le_bytes());
+            for v in &b.top {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.push(b.has_percentiles as u8);
+            if self.config.log_scale {
+                out.extend_from_slice(&(b.log.len() as u32).to_le_bytes());
+                for s in &b.log {
+                    encode_scale(s, out);
+                }
+            }
+            encode_scale(&b.squares, out);
+            if self.config.relative_error > 0 {
+                out.extend_from_slice(&b.sketch.zero.to_le_bytes());
+                out.extend_from_slice(&(b.sketch.bins.len() as u32).to_le_bytes());
+                for (bin, count) in &b.sketch.bins {
+                    out.extend_from_slice(&bin.to_le_bytes());
+                    out.extend_from_slice(&count.to_le_bytes());
+                }
+            }
+            #[cfg(feature = "tdigest")]
+            if self.config.estimator == PercentileEstimator::TDigest {
+                encode_centroids(&b.digest.centroids, out);
+                encode_centroids(&b.digest.buffer, out);
+            }
+        }
+    }
+
+    /// byte size of the [`Histogram::encode`] dump to preallocate the buffer,
+    /// exact if every bucket holds all the scales and the top values, above otherwise
+    pub fn serialized_size_hint(&self) -> usize {
+        let scales = self.config.percentiles.len() + 1;
+        let top = self.config.top_n.unwrap_or(0).min(self.sample_count() as usize);
+        let bucket = 4 + 4 + scales * 24 + 16 + 4 + top * 8 + 1 + if self.config.log_scale { 4 + scales * 24 } else { 0 } + 24;
+        let config = 1 + 4 + self.config.percentiles.len() * 2 + 1 + 2 + 1 + 4 + 4 + 1 + 1 + 1
+            + 1 + if self.config.max_valid_value.is_some() { 8 } else { 0 } + 1 + 2 + 1 + 1 + 4 + 8 + 2 + 1 + 4;
+        let lifetime = 4 + self.lifetime.len() * 24;
+        let extremes = if self.config.extreme_log_size > 0 { 4 + self.extremes.len() * 12 } else { 0 };
+        let sketches: usize = if self.config.relative_error > 0 {
+            self.buckets.iter().map(|b| 8 + 4 + b.sketch.bins.len() * 12).sum()
+        } else {
+            0
+        };
+        #[cfg(feature = "tdigest")]
+        let sketches = sketches + if self.config.estimator == PercentileEstimator::TDigest {
+            self.buckets.iter().map(|b| 4 + 4 + (b.digest.centroids.len() + b.digest.buffer.len()) * 16).sum()
+        } else {
+            0
+        };
+        config + 4 + 16 + 16 + 8 + lifetime + extremes + 4 + self.buckets.len() * bucket + sketches
+    }
+
+    /// restore from the [`Histogram::encode`] dump, bucket ages are kept
+    pub fn decode(data: &[u8]) -> Result<Histogram, HistogramError> {
+        let mut r = Reader { data, pos: 0 };
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(HistogramError::UnsupportedVersion(version));
+        }
+        let len = r.u32()? as usize;
+        let mut percentiles = Vec::with_capacity(len.min(data.len()));
+        for _ in 0..len {
+            percentiles.push(r.u16()?);
+        }
+        let config = Config {
+            percentiles,
+            span_sec: r.u8()?,
+            live_time_sec: r.u16()?,
+            time_unit: match r.u8()? {
+                0 => TimeUnit::Nanos,
+                1 => TimeUnit::Micros,
+                2 => TimeUnit::Millis,
+                u => return Err(HistogramError::Malformed(format!("time unit {}", u))),
+            },
+            top_n: match r.u32()? {
+                0 => None,
+                n => Some(n as usize - 1),
+            },
+            min_samples_for_percentile: r.u32()? as usize,
+            rounding: match r.u8()? {
+                0 => Rounding::RoundDown,
+                1 => Rounding::RoundNearest,
+                u => return Err(HistogramError::Malformed(format!("rounding {}", u))),
+            },
+            tail_retention: match r.u8()? {
+                0 => None,
+                n => Some(n),
+            },
+            tail: match r.u8()? {
+                0 => Tail::Both,
+                1 => Tail::Upper,
+                2 => Tail::Lower,
+                u => return Err(HistogramError::Malformed(format!("tail {}", u))),
+            },
+            ..Default::default()
+        };
+        let config = match r.u8()? {
+            0 => config,
+            u @ (1 | 2) => Config { max_valid_value: Some(r.u64()?), clamp_invalid: u == 2, ..config },
+            u => return Err(HistogramError::Malformed(format!("max valid value {}", u))),
+        };
+        let config = Config {
+            estimator: match r.u8()? {
+                0 => PercentileEstimator::RangeBand,
+                1 => PercentileEstimator::WeightedBuckets,
+                #[cfg(feature = "tdigest")]
+                2 => PercentileEstimator::TDigest,
+                u => return Err(HistogramError::Malformed(format!("estimator {}", u))),
+            },
+            warmup_sec: r.u16()?,
+            log_scale: r.u8()? != 0,
+            window: match r.u8()? {
+                0 => PercentileWindow::Live,
+                1 => PercentileWindow::Lifetime,
+                u => return Err(HistogramError::Malformed(format!("window {}", u))),
+            },
+            extreme_log_size: r.u32()? as usize,
+            min_range_width: r.u64()?,
+            relative_error: r.u16()?,
+            lifetime_only: r.u8()? != 0,
+            skew: f32::from_bits(r.u32()?),
+            ..config
+        };
+        config.clone().validate().map_err(HistogramError::InvalidConfig)?;
+        let elapsed = Duration::from_secs(r.u32()? as u64);
+        let now = Instant::now();
+        let mut h = Histogram::with_start(config, now.checked_sub(elapsed).unwrap_or(now));
+        h.range = r.range()?;
+        h.range_lifetime = r.range()?;
+        h.rejected = r.u64()?;
+        let len = r.u32()? as usize;
+        for _ in 0..len {
+            let s = r.scale()?;
+            h.lifetime.push(s);
+        }
+        if h.config.extreme_log_size > 0 {
+            let len = r.u32()? as usize;
+            for _ in 0..len {
+                let extreme = (r.u32()?, r.u64()?);
+                h.extremes.push_back(extreme);
+            }
+        }
+        let len = r.u32()? as usize;
+        for _ in 0..len {
+            let time = r.u32()?;
+            let scales = r.u32()? as usize;
+            let mut scale = Vec::with_capacity(scales.min(data.len()));
+            for _ in 0..scales {
+                scale.push(r.scale()?);
+            }
+            if scale.is_empty() {
+                return Err(HistogramError::Malformed("bucket without scale".to_string()));
+            }
+            let range = r.range()?;
+            let len = r.u32()? as usize;
+            let mut top = Vec::with_capacity(len.min(data.len()));
+            for _ in 0..len {
+                top.push(r.u64()?);
+            }
+            let has_percentiles = r.u8()? != 0;
+            let mut log = Vec::new();
+            if h.config.log_scale {
+                let len = r.u32()? as usize;
+                for _ in 0..len {
+                    log.push(r.scale()?);
+                }
+            }
+            let squares = r.scale()?;
+            let mut b = Bucket { time, scale, range, top, has_percentiles, log, squares, ..Bucket::new(time) };
+            b.check_percentiles(h.config.percentiles.len());
+            if h.config.relative_error > 0 {
+                b.sketch.zero = r.u64()?;
+                let len = r.u32()? as usize;
+                for _ in 0..len {
+                    let bin = r.i32()?;
+                    b.sketch.bins.insert(bin, r.u64()?);
+                }
+            }
+            #[cfg(feature = "tdigest")]
+            if h.config.estimator == PercentileEstimator::TDigest {
+                b.digest.centroids = r.centroids()?;
+                b.digest.buffer = r.centroids()?;
+            }
+            h.buckets.push_back(b);
+        }
+        h.totals = h.fold_scales();
+        Ok(h)
+    }
+
+    /// [`Histogram::encode`] dump prefixed by the epoch in nanoseconds, see [`Histogram::restore`]
+    pub fn checkpoint(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + self.serialized_size_hint());
+        let epoch = self.epoch.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        out.extend_from_slice(&(epoch.as_nanos() as u64).to_le_bytes());
+        self.encode(&mut out);
+        out
+    }
+
+    /// restore from the [`Histogram::checkpoint`] to continue the timeline of the epoch,
+    /// so the buckets are aged by the downtime and the stale ones evicted on the next append
+    pub fn restore(data: &[u8]) -> Result<Histogram, HistogramError> {
+        let mut r = Reader { data, pos: 0 };
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_nanos(r.u64()?);
+        let mut h = Histogram::decode(&data[r.pos..])?;
+        let elapsed = SystemTime::now().duration_since(epoch).unwrap_or_default();
+        match Instant::now().checked_sub(elapsed) {
+            Some(start) if elapsed.as_secs() >= h.now() as u64 => {
+                h.start = start;
+                h.epoch = epoch;
+            }
+            // the wall clock went back keeping the ages, or the downtime is beyond the monotonic clock
+            _ => {
+                let downtime = elapsed.as_secs().saturating_sub(h.now() as u64);
+                let downtime = downtime.min(u32::MAX as u64) as u32;
+                h.buckets.retain_mut(|b| match b.time.checked_sub(downtime) {
+                    Some(time) => {
+                        b.time = time;
+                        true
+                    }
+                    None => false,
+                });
+                h.range = h.buckets_range();
+                h.totals = h.fold_scales();
+            }
+        }
+        Ok(h)
+    }
+}
+
+#[inline]
+fn encode_scale(scale: &Scale, out: &mut Vec<u8>) {
+    out.extend_from_slice(&scale.sum.to_le_bytes());
+    out.extend_from_slice(&scale.power.to_le_bytes());
+    out.extend_from_slice(&scale.count.to_le_bytes());
+}
+
+/// count, then `(mean, weight)` of a t-digest
+#[cfg(feature = "tdigest")]
+fn encode_centroids(centroids: &[(f64, u64)], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(centroids.len() as u32).to_le_bytes());
+    for (mean, weight) in centroids {
+        out.extend_from_slice(&mean.to_bits().to_le_bytes());
+        out.extend_from_slice(&weight.to_le_bytes());
+    }
+}
+
+#[inline]
+fn encode_range(range: &Range, out: &mut Vec<u8>) {
+    out.extend_from_slice(&range.min_max.0.to_le_bytes());
+    out.extend_from_slice(&range.min_max.1.to_le_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], HistogramError> {
+        let end = self.pos + N;
+        if end > self.data.len() {
+            return Err(HistogramError::Truncated);
+        }
+        let mut buf = [0u8; N];
+        buf.copy_from_slice(&self.data[self.pos..end]);
+        self.pos = end;
+        Ok(buf)
+    }
+
+    fn u8(&mut self) -> Result<u8, HistogramError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, HistogramError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, HistogramError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, HistogramError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, HistogramError> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    #[cfg(feature = "tdigest")]
+    fn centroids(&mut self) -> Result<Vec<(f64, u64)>, HistogramError> {
+        let len = self.u32()? as usize;
+        let mut centroids = Vec::with_capacity(len.min(self.data.len()));
+        for _ in 0..len {
+            centroids.push((f64::from_bits(self.u64()?), self.u64()?));
+        }
+        Ok(centroids)
+    }
+
+    fn range(&mut self) -> Result<Range, HistogramError> {
+        Ok(Range { min_max: (self.u64()?, self.u64()?) })
+    }
+
+    fn scale(&mut self) -> Result<Scale, HistogramError> {
+        Ok(Scale { sum: self.u64()?, power: self.u64()?, count: self.u64()? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut h = Histogram::new(Config {
+            percentiles: vec![9000, 9990],
+            time_unit: TimeUnit::Nanos,
+            top_n: Some(2),
+            min_samples_for_percentile: 3,
+            rounding: Rounding::RoundNearest,
+            tail_retention: Some(2),
+            tail: Tail::Lower,
+            max_valid_value: Some(1000),
+            clamp_invalid: true,
+            estimator: PercentileEstimator::WeightedBuckets,
+            warmup_sec: 1,
+            log_scale: true,
+            window: PercentileWindow::Lifetime,
+            extreme_log_size: 2,
+            min_range_width: 10,
+            skew: -0.25,
+            relative_error: 50,
+            ..Default::default()
+        });
+        h.append_at(0, 5);
+        h.append_at(3, 100);
+        h.append_at(3, 50);
+        h.append_at(3, 5000);
+        let mut out = Vec::new();
+        h.encode(&mut out);
+        let d = Histogram::decode(&out).unwrap();
+        assert_eq!(d.config, h.config);
+        assert_eq!(d.buckets, h.buckets);
+        assert_eq!(d.range, h.range);
+        assert_eq!(d.range_lifetime, h.range_lifetime);
+        assert_eq!(d.sample_count_p(90), h.sample_count_p(90));
+        assert_eq!(d.average(), h.average());
+        assert_eq!(d.total_scale(), h.total_scale());
+        assert_eq!(d.rejected_count(), 1);
+        assert_eq!(d.lifetime, h.lifetime);
+        assert_eq!(d.extremes, h.extremes);
+        // the first bucket is a top value short
+        assert_eq!(h.serialized_size_hint(), out.len() + 8);
+
+        let mut h = Histogram::new(Config { lifetime_only: true, ..Default::default() });
+        h.append(7);
+        out.clear();
+        h.encode(&mut out);
+        assert_eq!(Histogram::decode(&out).unwrap().config, h.config);
+        assert_eq!(h.serialized_size_hint(), out.len());
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000, 9990], top_n: Some(2), ..Default::default() });
+        let mut out = Vec::new();
+        h.encode(&mut out);
+        assert_eq!(h.serialized_size_hint(), out.len());
+        for t in 0..20 {
+            for v in 0..5 {
+                h.append_at(t * 2, v * 10);
+            }
+        }
+        out.clear();
+        h.encode(&mut out);
+        assert_eq!(h.serialized_size_hint(), out.len());
+        // a single sample of the top two per bucket
+        h.append_at(50, 1);
+        out.clear();
+        h.encode(&mut out);
+        assert_eq!(h.serialized_size_hint(), out.len() + 8);
+    }
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let config = Config { percentiles: vec![9000], span_sec: 1, live_time_sec: 30, ..Default::default() };
+        let mut h = Histogram::with_start(config, Instant::now() - Duration::from_secs(10));
+        for v in 1..=10 {
+            h.append(v);
+        }
+        let data = h.checkpoint();
+        let mut r = Histogram::restore(&data).unwrap();
+        assert_eq!(r.epoch(), h.epoch());
+        assert!(r.now().abs_diff(h.now()) <= 1);
+        assert_eq!(r.buckets, h.buckets);
+        // the restored samples once, then the new on the same timeline
+        for v in 11..=15 {
+            r.append(v);
+        }
+        assert_eq!(r.sample_count(), 15);
+        assert_eq!(r.range.min_max, (1, 15));
+
+        // checkpointed a minute ago, the buckets are stale
+        let mut old = data.clone();
+        let epoch = u64::from_le_bytes(old[..8].try_into().unwrap()) - 60_000_000_000;
+        old[..8].copy_from_slice(&epoch.to_le_bytes());
+        let mut r = Histogram::restore(&old).unwrap();
+        assert!(r.now() >= 70);
+        r.append(100);
+        assert_eq!(r.sample_count(), 1);
+        assert_eq!(Histogram::restore(&data[..4]).err(), Some(HistogramError::Truncated));
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let mut h = Histogram::new(Config::default());
+        h.append(1);
+        let mut out = Vec::new();
+        h.encode(&mut out);
+        for len in 0..out.len() {
+            assert_eq!(Histogram::decode(&out[..len]).err(), Some(HistogramError::Truncated));
+        }
+        out[0] = 0;
+        assert_eq!(Histogram::decode(&out).err(), Some(HistogramError::UnsupportedVersion(0)));
+        // of the 32 bit power
+        out[0] = 1;
+        assert_eq!(Histogram::decode(&out).err(), Some(HistogramError::UnsupportedVersion(1)));
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::Config;
+
+/// Lock-free sliding window of sample counts and sums only, no percentiles or ranges.
+/// Buckets are recycled in place, so a bucket turning over may lose samples racing with the reset.
+#[derive(Debug)]
+pub struct CountOnlyHistogram {
+    start: Instant,
+    span_sec: u32,
+    /// ring of the live time, single lifetime bucket if no live time
+    buckets: Vec<CountBucket>,
+}
+
+#[derive(Debug, Default)]
+struct CountBucket {
+    /// span id + 1 of the bucket fillup, 0 is never used
+    tag: AtomicU32,
+    /// saturated sum
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+impl CountOnlyHistogram {
+    /// use span and live time of the config
+    pub fn new(config: &Config) -> CountOnlyHistogram {
+        let span_sec = (config.span_sec as u32).max(1);
+        let len = if config.live_time_sec == 0 { 1 } else { config.live_time_sec as u32 / span_sec + 1 };
+        CountOnlyHistogram {
+            start: Instant::now(),
+            span_sec,
+            buckets: (0..len).map(|_| CountBucket::default()).collect(),
+        }
+    }
+
+    /// span id + 1 of now
+    #[inline]
+    fn tag(&self) -> u32 {
+        if self.buckets.len() == 1 {
+            return 1;
+        }
+        let time = self.start.elapsed().as_secs() / self.span_sec as u64;
+        if time >= u32::MAX as u64 { u32::MAX } else { time as u32 + 1 }
+    }
+
+    pub fn append(&self, value: u64) {
+        let tag = self.tag();
+        let b = &self.buckets[tag as usize % self.buckets.len()];
+        let current = b.tag.load(Ordering::Acquire);
+        if current != tag && b.tag.compare_exchange(current, tag, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+            b.sum.store(0, Ordering::Release);
+            b.count.store(0, Ordering::Release);
+        }
+        let _ = b.sum.fetch_update(Ordering::AcqRel, Ordering::Acquire, |s| Some(s.saturating_add(value)));
+        b.count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// sum and count of the live buckets
+    fn fold(&self) -> (u128, u64) {
+        let tag = self.tag();
+        let oldest = tag.saturating_sub(self.buckets.len() as u32 - 1);
+        let mut r = (0u128, 0u64);
+        for b in &self.buckets {
+            let t = b.tag.load(Ordering::Acquire);
+            if t != 0 && t >= oldest && t <= tag {
+                r.0 += b.sum.load(Ordering::Acquire) as u128;
+                r.1 += b.count.load(Ordering::Acquire);
+            }
+        }
+        r
+    }
+
+    /// count of the live samples
+    pub fn count(&self) -> u64 {
+        self.fold().1
+    }
+
+    /// average of the live samples
+    pub fn mean(&self) -> Option<u64> {
+        let (sum, count) = self.fold();
+        if count == 0 {
+            None
+        } else {
+            Some((sum / count as u128) as u64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    const THREADS: u64 = 8;
+    const SAMPLES: u64 = 10000;
+
+    #[test]
+    fn test_count_only() {
+        let h = CountOnlyHistogram::new(&Config::default());
+        assert_eq!(h.mean(), None);
+        h.append(1);
+        h.append(3);
+        assert_eq!(h.count(), 2);
+        assert_eq!(h.mean(), Some(2));
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let h = Arc::new(CountOnlyHistogram::new(&Config::default()));
+        let threads: Vec<_> = (0..THREADS).map(|_| {
+            let h = h.clone();
+            thread::spawn(move || for x in 0..SAMPLES { h.append(x) })
+        }).collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(h.count(), THREADS * SAMPLES);
+        assert_eq!(h.mean(), Some((SAMPLES - 1) / 2));
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HistogramError {
+    /// recording would saturate a counter
+    Overflow,
+    /// histograms configured differently
+    ConfigMismatch,
+    /// too few samples to report
+    InsufficientSamples,
+    /// percentile is not configured
+    UnknownPercentile(String),
+    /// not enough data to decode
+    Truncated,
+    /// unknown format version
+    UnsupportedVersion(u8),
+    /// decoded config did not pass validation
+    InvalidConfig(String),
+    /// inconsistent data
+    Malformed(String),
+    /// within the warmup period after start
+    Warmup,
+    /// window query of a lifetime only histogram
+    NoWindow,
+}
+
+impl Display for HistogramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            HistogramError::Overflow => write!(f, "counter overflow"),
+            HistogramError::ConfigMismatch => write!(f, "config mismatch"),
+            HistogramError::InsufficientSamples => write!(f, "insufficient samples"),
+            HistogramError::Warmup => write!(f, "warming up"),
+            HistogramError::NoWindow => write!(f, "no window of a lifetime only histogram"),
+            HistogramError::UnknownPercentile(e) => write!(f, "{}", e),
+            HistogramError::Truncated => write!(f, "truncated data"),
+            HistogramError::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+            HistogramError::InvalidConfig(e) => write!(f, "invalid config: {}", e),
+            HistogramError::Malformed(e) => write!(f, "malformed data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HistogramError {}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use crate::{Histogram, HistogramError};
+
+/// Query only view of a closed window, the percentile folds are computed once at freeze,
+/// a clone shares the same aggregates.
+#[derive(Clone, Debug)]
+pub struct FrozenHistogram {
+    inner: Arc<Frozen>,
+}
+
+#[derive(Debug)]
+struct Frozen {
+    histogram: Histogram,
+    warming_up: bool,
+    mean: Option<u64>,
+    /// by the configured percentiles
+    averages: Vec<Result<u64, HistogramError>>,
+    /// `(p, quantile)` of the configured percentiles
+    quantiles: Vec<(f64, Result<u64, HistogramError>)>,
+}
+
+impl Histogram {
+    /// stop recording and fold the aggregates of the queries once
+    pub fn freeze(self) -> FrozenHistogram {
+        let scales = self.scales();
+        let percentiles = &self.config.percentiles;
+        let averages = (1..percentiles.len() + 1).map(|pid| self.checked_estimate(pid, &scales[pid])).collect();
+        let quantiles = percentiles.iter()
+            .map(|p| {
+                let p = *p as f64 / 10000f64;
+                (p, self.quantile(p))
+            })
+            .collect();
+        FrozenHistogram {
+            inner: Arc::new(Frozen {
+                warming_up: self.warming_up(),
+                mean: self.mean(),
+                averages,
+                quantiles,
+                histogram: self,
+            }),
+        }
+    }
+}
+
+impl FrozenHistogram {
+    /// see [`Histogram::mean`]
+    pub fn mean(&self) -> Option<u64> {
+        self.inner.mean
+    }
+
+    /// see [`Histogram::average_p`]
+    pub fn average_p(&self, percentile: u8) -> Result<u64, HistogramError> {
+        let pid = self.inner.histogram.config.find(percentile)?;
+        self.inner.averages[pid - 1].clone()
+    }
+
+    /// see [`Histogram::quantile`], a configured percentile is precomputed
+    pub fn quantile(&self, p: f64) -> Result<u64, HistogramError> {
+        if let Some((_, q)) = self.inner.quantiles.iter().find(|(x, _)| *x == p) {
+            return q.clone();
+        }
+        if self.inner.warming_up {
+            return Err(HistogramError::Warmup);
+        }
+        self.inner.histogram.quantile(p)
+    }
+
+    /// the frozen histogram for other queries
+    pub fn histogram(&self) -> &Histogram {
+        &self.inner.histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_freeze() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000, 9900], ..Default::default() });
+        for v in 0..=1000 {
+            h.append(v);
+        }
+        let live = h.clone();
+        let frozen = h.freeze();
+        let copy = frozen.clone();
+        assert!(Arc::ptr_eq(&frozen.inner, &copy.inner));
+        for _ in 0..3 {
+            assert_eq!(copy.mean(), live.mean());
+            assert_eq!(copy.average_p(90), live.average_p(90));
+            assert_eq!(copy.average_p(99), live.average_p(99));
+            assert_eq!(copy.quantile(0.9), live.quantile(0.9));
+            assert_eq!(copy.quantile(0.5), live.quantile(0.5));
+        }
+        assert!(matches!(copy.average_p(50), Err(HistogramError::UnknownPercentile(_))));
+        assert_eq!(copy.histogram().sample_count(), 1001);
+
+        let h = Histogram::new(Config { percentiles: vec![9000], warmup_sec: 60, ..Default::default() }).freeze();
+        assert_eq!(h.average_p(90), Err(HistogramError::Warmup));
+        assert_eq!(h.quantile(0.5), Err(HistogramError::Warmup));
+    }
+}
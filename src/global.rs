@@ -0,0 +1,60 @@
+//! Process wide histogram for apps with a single global metric,
+//! to record from anywhere without passing a handle around.
+//! Opt-in: nothing is allocated until [`install`].
+
+use std::sync::OnceLock;
+
+use crate::{Config, SharedHistogram, Snapshot};
+
+static GLOBAL: OnceLock<SharedHistogram> = OnceLock::new();
+
+/// create the global histogram, false if already installed, the config of the first install is kept
+pub fn install(config: Config) -> bool {
+    let mut installed = false;
+    GLOBAL.get_or_init(|| {
+        installed = true;
+        SharedHistogram::new(config)
+    });
+    installed
+}
+
+/// append to the global histogram, dropped if not installed
+#[inline]
+pub fn record(value: u64) {
+    if let Some(h) = GLOBAL.get() {
+        h.append(value);
+    }
+}
+
+/// summary of the global histogram, none if not installed
+pub fn snapshot() -> Option<Snapshot> {
+    GLOBAL.get().map(SharedHistogram::snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_global() {
+        record(1);
+        assert!(snapshot().is_none());
+        assert!(install(Config { percentiles: vec![9000], ..Default::default() }));
+        assert!(!install(Config::default()));
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for v in 1..=100 {
+                        record(v);
+                    }
+                });
+            }
+        });
+        let s = snapshot().unwrap();
+        assert_eq!(s.count, 200);
+        assert_eq!((s.min, s.max), (1, 100));
+        assert_eq!(s.mean, 50);
+        assert_eq!(s.percentiles.len(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::Histogram;
+
+/// Counts matrix of time buckets by value bands
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeatmapData {
+    /// bucket times, oldest first
+    pub times: Vec<u32>,
+    /// inclusive value bounds of each band
+    pub bands: Vec<(u64, u64)>,
+    /// counts by `[time][band]`
+    pub counts: Vec<Vec<u64>>,
+}
+
+impl Histogram {
+    /// distribute every bucket count across `value_bands` equal slices of the range,
+    /// uniform within a bucket range, each row sums up to the bucket count
+    pub fn heatmap(&self, value_bands: usize) -> HeatmapData {
+        let (min, max) = self.range.min_max;
+        if value_bands == 0 || min > max {
+            return HeatmapData { times: vec![], bands: vec![], counts: vec![] };
+        }
+        // continuous value domain of [min, max + 1)
+        let width = (max - min) as f64 + 1f64;
+        let edge = |i: usize| min as f64 + width * i as f64 / value_bands as f64;
+        let first = |i: usize| edge(i).ceil() as u64;
+        let bands = (0..value_bands)
+            .map(|i| (first(i), first(i + 1).saturating_sub(1).max(first(i))))
+            .collect();
+        let mut times = Vec::with_capacity(self.buckets.len());
+        let mut counts = Vec::with_capacity(self.buckets.len());
+        for b in self.buckets.iter().rev() {
+            times.push(b.time);
+            let count = b.scale[0].count;
+            let mut row = vec![0f64; value_bands];
+            if count > 0 {
+                let (lo, hi) = (b.range.min_max.0 as f64, b.range.min_max.1 as f64 + 1f64);
+                for (i, c) in row.iter_mut().enumerate() {
+                    let overlap = hi.min(edge(i + 1)) - lo.max(edge(i));
+                    if overlap > 0f64 {
+                        *c = count as f64 * overlap / (hi - lo);
+                    }
+                }
+            }
+            counts.push(round_row(&row, count));
+        }
+        HeatmapData { times, bands, counts }
+    }
+}
+
+/// round keeping the total, the largest remainders get the rest
+pub(crate) fn round_row(row: &[f64], total: u64) -> Vec<u64> {
+    let mut r: Vec<u64> = row.iter().map(|c| c.floor() as u64).collect();
+    let rest = total.saturating_sub(r.iter().sum());
+    let mut order: Vec<usize> = (0..row.len()).collect();
+    order.sort_by(|a, b| (row[*b] - row[*b].floor()).total_cmp(&(row[*a] - row[*a].floor())));
+    for i in order.into_iter().cycle().take(rest as usize) {
+        r[i] += 1;
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_heatmap() {
+        let mut h = Histogram::new(Config::default());
+        assert!(h.heatmap(4).counts.is_empty());
+        for x in 0..100 {
+            h.append_at(0, x);
+        }
+        for x in 0..7 {
+            h.append_at(3, 10 + x);
+        }
+        h.append_at(6, 99);
+        let m = h.heatmap(4);
+        assert_eq!(m.times, vec![0, 3, 6]);
+        assert_eq!(m.bands, vec![(0, 24), (25, 49), (50, 74), (75, 99)]);
+        assert_eq!(m.counts.len(), 3);
+        assert!(m.counts.iter().all(|r| r.len() == 4));
+        assert_eq!(m.counts[0], vec![25, 25, 25, 25]);
+        assert_eq!(m.counts[1], vec![7, 0, 0, 0]);
+        assert_eq!(m.counts[2], vec![0, 0, 0, 1]);
+        for (row, b) in m.counts.iter().zip(h.buckets.iter().rev()) {
+            assert_eq!(row.iter().sum::<u64>(), b.scale[0].count);
+        }
+        let m = h.heatmap(3);
+        assert_eq!(m.counts[0].iter().sum::<u64>(), 100);
+    }
+}
@@ -0,0 +1,515 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::{Bucket, Config, Histogram, HistogramError, PercentileEstimator, PercentileWindow, Range, Rounding, Scale, Tail, TimeUnit};
+
+/// json schema version
+const VERSION: u64 = 1;
+
+impl Histogram {
+    /// json of the version, config, elapsed seconds, ranges and buckets of time offsets from start,
+    /// a scale is `[sum, power, count]` and a range is `[min, max]`
+    pub fn to_json_v1(&self) -> String {
+        let c = &self.config;
+        let mut out = String::new();
+        let _ = write!(out, "{{\"version\":{},\"config\":{{\"percentiles\":{},\"span_sec\":{},\"live_time_sec\":{}",
+            VERSION, array(&c.percentiles), c.span_sec, c.live_time_sec);
+        let _ = write!(out, ",\"time_unit\":\"{}\",\"top_n\":{},\"min_samples_for_percentile\":{}",
+            match c.time_unit {
+                TimeUnit::Nanos => "ns",
+                TimeUnit::Micros => "us",
+                TimeUnit::Millis => "ms",
+            },
+            option(c.top_n), c.min_samples_for_percentile);
+        let _ = write!(out, ",\"rounding\":\"{}\",\"tail_retention\":{},\"tail\":\"{}\"",
+            match c.rounding {
+                Rounding::RoundDown => "down",
+                Rounding::RoundNearest => "nearest",
+            },
+            option(c.tail_retention),
+            match c.tail {
+                Tail::Both => "both",
+                Tail::Upper => "upper",
+                Tail::Lower => "lower",
+            });
+        let _ = write!(out, ",\"max_valid_value\":{},\"clamp_invalid\":{},\"estimator\":\"{}\",\"warmup_sec\":{},\"log_scale\":{},\"window\":\"{}\",\"extreme_log_size\":{},\"min_range_width\":{},\"relative_error\":{},\"lifetime_only\":{},\"skew\":\"{}\"}}",
+            option(c.max_valid_value), c.clamp_invalid,
+            match c.estimator {
+                PercentileEstimator::RangeBand => "band",
+                PercentileEstimator::WeightedBuckets => "weighted",
+                #[cfg(feature = "tdigest")]
+                PercentileEstimator::TDigest => "tdigest",
+            },
+            c.warmup_sec, c.log_scale,
+            match c.window {
+                PercentileWindow::Live => "live",
+                PercentileWindow::Lifetime => "lifetime",
+            },
+            c.extreme_log_size, c.min_range_width, c.relative_error, c.lifetime_only, c.skew);
+        let _ = write!(out, ",\"elapsed\":{},\"range\":{},\"range_lifetime\":{},\"rejected\":{}",
+            self.now(), range(&self.range), range(&self.range_lifetime), self.rejected);
+        let lifetime: Vec<String> = self.lifetime.iter().map(scale).collect();
+        let _ = write!(out, ",\"lifetime\":[{}]", lifetime.join(","));
+        if c.extreme_log_size > 0 {
+            let extremes: Vec<String> = self.extremes.iter().map(|(t, v)| format!("[{},{}]", t, v)).collect();
+            let _ = write!(out, ",\"extremes\":[{}]", extremes.join(","));
+        }
+        out.push_str(",\"buckets\":[");
+        for (i, b) in self.buckets.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let scales: Vec<String> = b.scale.iter().map(scale).collect();
+            let _ = write!(out, "{{\"time\":{},\"scale\":[{}],\"range\":{},\"top\":{},\"has_percentiles\":{}",
+                b.time, scales.join(","), range(&b.range), array(&b.top), b.has_percentiles);
+            if c.log_scale {
+                let log: Vec<String> = b.log.iter().map(scale).collect();
+                let _ = write!(out, ",\"log\":[{}]", log.join(","));
+            }
+            let _ = write!(out, ",\"squares\":{}", scale(&b.squares));
+            if c.relative_error > 0 {
+                let bins: Vec<String> = b.sketch.bins.iter().map(|(bin, count)| format!("[{},{}]", bin, count)).collect();
+                let _ = write!(out, ",\"sketch\":{{\"zero\":{},\"bins\":[{}]}}", b.sketch.zero, bins.join(","));
+            }
+            #[cfg(feature = "tdigest")]
+            if c.estimator == PercentileEstimator::TDigest {
+                let _ = write!(out, ",\"digest\":{{\"centroids\":{},\"buffer\":{}}}", centroids(&b.digest.centroids), centroids(&b.digest.buffer));
+            }
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// restore from the json of a known version, bucket ages are kept
+    pub fn from_json(json: &str) -> Result<Histogram, HistogramError> {
+        let mut p = Parser { data: json.as_bytes(), pos: 0, depth: 0 };
+        let root = p.value()?;
+        p.space();
+        if p.pos < p.data.len() {
+            return Err(malformed("trailing data"));
+        }
+        match root.get("version")?.num()? {
+            VERSION => from_v1(&root),
+            v => Err(HistogramError::UnsupportedVersion(v.min(u8::MAX as u64) as u8)),
+        }
+    }
+}
+
+fn from_v1(root: &Json) -> Result<Histogram, HistogramError> {
+    let c = root.get("config")?;
+    let config = Config {
+        percentiles: c.get("percentiles")?.arr()?.iter().map(|p| p.num().and_then(narrow)).collect::<Result<_, _>>()?,
+        span_sec: narrow(c.get("span_sec")?.num()?)?,
+        live_time_sec: narrow(c.get("live_time_sec")?.num()?)?,
+        time_unit: match c.get("time_unit")?.str()? {
+            "ns" => TimeUnit::Nanos,
+            "us" => TimeUnit::Micros,
+            "ms" => TimeUnit::Millis,
+            u => return Err(malformed(&format!("time unit {}", u))),
+        },
+        top_n: c.get("top_n")?.option()?.map(narrow).transpose()?,
+        min_samples_for_percentile: narrow(c.get("min_samples_for_percentile")?.num()?)?,
+        rounding: match c.get("rounding")?.str()? {
+            "down" => Rounding::RoundDown,
+            "nearest" => Rounding::RoundNearest,
+            u => return Err(malformed(&format!("rounding {}", u))),
+        },
+        tail_retention: c.get("tail_retention")?.option()?.map(narrow).transpose()?,
+        tail: match c.get("tail")?.str()? {
+            "both" => Tail::Both,
+            "upper" => Tail::Upper,
+            "lower" => Tail::Lower,
+            u => return Err(malformed(&format!("tail {}", u))),
+        },
+        max_valid_value: c.get("max_valid_value")?.option()?,
+        clamp_invalid: c.get("clamp_invalid")?.bool()?,
+        estimator: match c.get("estimator")?.str()? {
+            "band" => PercentileEstimator::RangeBand,
+            "weighted" => PercentileEstimator::WeightedBuckets,
+            #[cfg(feature = "tdigest")]
+            "tdigest" => PercentileEstimator::TDigest,
+            u => return Err(malformed(&format!("estimator {}", u))),
+        },
+        warmup_sec: narrow(c.get("warmup_sec")?.num()?)?,
+        log_scale: c.get("log_scale")?.bool()?,
+        window: match c.get("window")?.str()? {
+            "live" => PercentileWindow::Live,
+            "lifetime" => PercentileWindow::Lifetime,
+            u => return Err(malformed(&format!("window {}", u))),
+        },
+        extreme_log_size: narrow(c.get("extreme_log_size")?.num()?)?,
+        min_range_width: c.get("min_range_width")?.num()?,
+        relative_error: narrow(c.get("relative_error")?.num()?)?,
+        lifetime_only: c.get("lifetime_only")?.bool()?,
+        skew: c.get("skew")?.str()?.parse().map_err(|_| malformed("skew"))?,
+    };
+    config.clone().validate().map_err(HistogramError::InvalidConfig)?;
+    let elapsed = Duration::from_secs(root.get("elapsed")?.num()?);
+    let now = Instant::now();
+    let mut h = Histogram::with_start(config, now.checked_sub(elapsed).unwrap_or(now));
+    h.range = root.get("range")?.range()?;
+    h.range_lifetime = root.get("range_lifetime")?.range()?;
+    h.rejected = root.get("rejected")?.num()?;
+    h.lifetime = root.get("lifetime")?.arr()?.iter().map(Json::scale).collect::<Result<_, _>>()?;
+    if h.config.extreme_log_size > 0 {
+        h.extremes = root.get("extremes")?.arr()?.iter()
+            .map(|e| match e.arr()? {
+                [time, value] => Ok((narrow(time.num()?)?, value.num()?)),
+                _ => Err(malformed("extreme")),
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    let log_scale = h.config.log_scale;
+    for b in root.get("buckets")?.arr()? {
+        let scale = b.get("scale")?.arr()?.iter()
+            .map(Json::scale)
+            .collect::<Result<Vec<_>, _>>()?;
+        if scale.is_empty() {
+            return Err(malformed("bucket without scale"));
+        }
+        let time = narrow(b.get("time")?.num()?)?;
+        let mut bucket = Bucket {
+            time,
+            scale,
+            range: b.get("range")?.range()?,
+            top: b.get("top")?.arr()?.iter().map(Json::num).collect::<Result<_, _>>()?,
+            has_percentiles: b.get("has_percentiles")?.bool()?,
+            log: if log_scale { b.get("log")?.arr()?.iter().map(Json::scale).collect::<Result<_, _>>()? } else { vec![] },
+            squares: b.get("squares")?.scale()?,
+            ..Bucket::new(time)
+        };
+        bucket.check_percentiles(h.config.percentiles.len());
+        if h.config.relative_error > 0 {
+            let sketch = b.get("sketch")?;
+            bucket.sketch.zero = sketch.get("zero")?.num()?;
+            for bin in sketch.get("bins")?.arr()? {
+                match bin.arr()? {
+                    [bin, count] => bucket.sketch.bins.insert(narrow(bin.num()?)?, count.num()?),
+                    _ => return Err(malformed("sketch bin")),
+                };
+            }
+        }
+        #[cfg(feature = "tdigest")]
+        if h.config.estimator == PercentileEstimator::TDigest {
+            let digest = b.get("digest")?;
+            bucket.digest.centroids = digest.get("centroids")?.centroids()?;
+            bucket.digest.buffer = digest.get("buffer")?.centroids()?;
+        }
+        h.buckets.push_back(bucket);
+    }
+    h.totals = h.fold_scales();
+    Ok(h)
+}
+
+#[inline]
+fn malformed(msg: &str) -> HistogramError {
+    HistogramError::Malformed(msg.to_string())
+}
+
+#[inline]
+fn narrow<T: TryFrom<u64>>(v: u64) -> Result<T, HistogramError> {
+    T::try_from(v).map_err(|_| malformed(&format!("out of range {}", v)))
+}
+
+fn array<T: ToString>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(T::to_string).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn option<T: ToString>(v: Option<T>) -> String {
+    v.map_or("null".to_string(), |v| v.to_string())
+}
+
+fn range(r: &Range) -> String {
+    format!("[{},{}]", r.min_max.0, r.min_max.1)
+}
+
+fn scale(s: &Scale) -> String {
+    format!("[{},{},{}]", s.sum, s.power, s.count)
+}
+
+/// `[mean, weight]` of a t-digest, the mean as a string for the integer only numbers
+#[cfg(feature = "tdigest")]
+fn centroids(c: &[(f64, u64)]) -> String {
+    let c: Vec<String> = c.iter().map(|(mean, weight)| format!("[\"{}\",{}]", mean, weight)).collect();
+    format!("[{}]", c.join(","))
+}
+
+/// parsed json, numbers are unsigned integers only
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Num(u64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Result<&Json, HistogramError> {
+        match self {
+            Json::Obj(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+                .ok_or_else(|| malformed(&format!("missing '{}'", key))),
+            _ => Err(malformed(&format!("no object for '{}'", key))),
+        }
+    }
+
+    fn num(&self) -> Result<u64, HistogramError> {
+        match self {
+            Json::Num(v) => Ok(*v),
+            _ => Err(malformed("not a number")),
+        }
+    }
+
+    fn option(&self) -> Result<Option<u64>, HistogramError> {
+        match self {
+            Json::Null => Ok(None),
+            v => v.num().map(Some),
+        }
+    }
+
+    fn bool(&self) -> Result<bool, HistogramError> {
+        match self {
+            Json::Bool(v) => Ok(*v),
+            _ => Err(malformed("not a bool")),
+        }
+    }
+
+    fn str(&self) -> Result<&str, HistogramError> {
+        match self {
+            Json::Str(v) => Ok(v),
+            _ => Err(malformed("not a string")),
+        }
+    }
+
+    fn arr(&self) -> Result<&[Json], HistogramError> {
+        match self {
+            Json::Arr(v) => Ok(v),
+            _ => Err(malformed("not an array")),
+        }
+    }
+
+    fn range(&self) -> Result<Range, HistogramError> {
+        match self.arr()? {
+            [min, max] => Ok(Range { min_max: (min.num()?, max.num()?) }),
+            _ => Err(malformed("range")),
+        }
+    }
+
+    fn scale(&self) -> Result<Scale, HistogramError> {
+        match self.arr()? {
+            [sum, power, count] => Ok(Scale { sum: sum.num()?, power: power.num()?, count: count.num()? }),
+            _ => Err(malformed("scale")),
+        }
+    }
+
+    #[cfg(feature = "tdigest")]
+    fn centroids(&self) -> Result<Vec<(f64, u64)>, HistogramError> {
+        self.arr()?.iter()
+            .map(|c| match c.arr()? {
+                [mean, weight] => Ok((mean.str()?.parse().map_err(|_| malformed("centroid"))?, weight.num()?)),
+                _ => Err(malformed("centroid")),
+            })
+            .collect()
+    }
+}
+
+/// nesting of the arrays and objects a parse fails beyond, to bound the recursion of untrusted input
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// arrays and objects open at the position
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn space(&mut self) {
+        while self.data.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<u8, HistogramError> {
+        let c = *self.data.get(self.pos).ok_or(HistogramError::Truncated)?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), HistogramError> {
+        if self.data[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(())
+        } else {
+            Err(malformed(&format!("expected '{}' at {}", word, self.pos)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, HistogramError> {
+        self.space();
+        match *self.data.get(self.pos).ok_or(HistogramError::Truncated)? {
+            b'n' => self.expect("null").map(|_| Json::Null),
+            b't' => self.expect("true").map(|_| Json::Bool(true)),
+            b'f' => self.expect("false").map(|_| Json::Bool(false)),
+            b'"' => self.string().map(Json::Str),
+            b'[' | b'{' => {
+                self.depth += 1;
+                if self.depth > MAX_DEPTH {
+                    return Err(malformed(&format!("nested deeper than {} at {}", MAX_DEPTH, self.pos)));
+                }
+                let v = if self.data[self.pos] == b'[' { self.array() } else { self.object() };
+                self.depth -= 1;
+                v
+            }
+            b'0'..=b'9' => {
+                let begin = self.pos;
+                while self.data.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                std::str::from_utf8(&self.data[begin..self.pos]).ok()
+                    .and_then(|v| v.parse().ok())
+                    .map(Json::Num)
+                    .ok_or_else(|| malformed(&format!("number at {}", begin)))
+            }
+            c => Err(malformed(&format!("unexpected '{}' at {}", c as char, self.pos))),
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, HistogramError> {
+        self.pos += 1;
+        let mut values = vec![];
+        self.space();
+        if self.data.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Json::Arr(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.space();
+            match self.next()? {
+                b',' => continue,
+                b']' => return Ok(Json::Arr(values)),
+                _ => return Err(malformed(&format!("expected ',' or ']' at {}", self.pos - 1))),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, HistogramError> {
+        self.pos += 1;
+        let mut fields = vec![];
+        self.space();
+        if self.data.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Json::Obj(fields));
+        }
+        loop {
+            self.space();
+            let key = self.string()?;
+            self.space();
+            self.expect(":")?;
+            fields.push((key, self.value()?));
+            self.space();
+            match self.next()? {
+                b',' => continue,
+                b'}' => return Ok(Json::Obj(fields)),
+                _ => return Err(malformed(&format!("expected ',' or '}}' at {}", self.pos - 1))),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, HistogramError> {
+        self.expect("\"")?;
+        let mut s = vec![];
+        loop {
+            match self.next()? {
+                b'"' => return String::from_utf8(s).map_err(|_| malformed("not utf-8 string")),
+                b'\\' => match self.next()? {
+                    c @ (b'"' | b'\\' | b'/') => s.push(c),
+                    b'n' => s.push(b'\n'),
+                    b't' => s.push(b'\t'),
+                    c => return Err(malformed(&format!("unsupported escape '{}'", c as char))),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut h = Histogram::new(Config {
+            percentiles: vec![9000, 9990],
+            time_unit: TimeUnit::Millis,
+            top_n: Some(2),
+            rounding: Rounding::RoundNearest,
+            tail: Tail::Upper,
+            max_valid_value: Some(1000),
+            estimator: PercentileEstimator::WeightedBuckets,
+            log_scale: true,
+            window: PercentileWindow::Lifetime,
+            extreme_log_size: 2,
+            min_range_width: 10,
+            skew: 0.5,
+            ..Default::default()
+        });
+        h.append_at(0, 5);
+        h.append_at(3, 100);
+        h.append_at(3, 50);
+        h.append_at(3, 5000);
+        let json = h.to_json_v1();
+        assert!(json.starts_with("{\"version\":1,"));
+        let d = Histogram::from_json(&json).unwrap();
+        assert_eq!(d.config, h.config);
+        assert_eq!(d.buckets, h.buckets);
+        assert_eq!(d.range, h.range);
+        assert_eq!(d.range_lifetime, h.range_lifetime);
+        assert_eq!(d.rejected_count(), 1);
+        assert_eq!(d.extremes, h.extremes);
+        assert_eq!(d.snapshot(), h.snapshot());
+        assert_eq!(d.to_json_v1(), json);
+
+        let d = Histogram::from_json(&Histogram::new(Config::default()).to_json_v1()).unwrap();
+        assert_eq!(d.sample_count(), 0);
+        // of the relative error without samples, the bins are empty
+        let c = Config { relative_error: 50, lifetime_only: true, ..Default::default() };
+        assert_eq!(Histogram::from_json(&Histogram::new(c.clone()).to_json_v1()).unwrap().config, c.into());
+    }
+
+    #[test]
+    fn test_json_percentiles_flag() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append_at(0, 10);
+        let json = h.to_json_v1();
+        assert!(Histogram::from_json(&json).unwrap().buckets[0].has_percentiles);
+        // the flag is of the scales loaded, not of the stored one
+        let short = json.replacen("\"scale\":[[10,0,1],[10,0,1]]", "\"scale\":[[10,0,1]]", 1);
+        assert_ne!(short, json);
+        let d = Histogram::from_json(&short).unwrap();
+        assert!(!d.buckets[0].has_percentiles);
+        assert_eq!(d.sample_count(), 1);
+        assert_eq!(d.sample_count_p(90), Ok(0));
+    }
+
+    #[test]
+    fn test_json_error() {
+        let json = Histogram::new(Config::default()).to_json_v1();
+        let v2 = json.replacen("\"version\":1", "\"version\":2", 1);
+        assert_eq!(Histogram::from_json(&v2).err(), Some(HistogramError::UnsupportedVersion(2)));
+        let v2 = json.replacen("\"version\":1", " \"version\" : 2 ", 1);
+        assert_eq!(Histogram::from_json(&v2).err(), Some(HistogramError::UnsupportedVersion(2)));
+        assert_eq!(Histogram::from_json(&json[..json.len() - 1]).err(), Some(HistogramError::Truncated));
+        assert!(matches!(Histogram::from_json("{}"), Err(HistogramError::Malformed(_))));
+        assert!(matches!(Histogram::from_json(&format!("{} 1", json)), Err(HistogramError::Malformed(_))));
+        let bad = json.replacen("\"span_sec\":1", "\"span_sec\":0", 1);
+        assert!(matches!(Histogram::from_json(&bad), Err(HistogramError::InvalidConfig(_))));
+        assert!(matches!(Histogram::from_json(&"[".repeat(1_000_000)), Err(HistogramError::Malformed(_))));
+        assert!(matches!(Histogram::from_json(&"{\"a\":".repeat(100)), Err(HistogramError::Malformed(_))));
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::{Config, Histogram, Snapshot};
+
+/// Histograms by a label, i.e. an endpoint, of a single config.
+/// A histogram is created on the first append of its label.
+#[derive(Clone, Debug)]
+pub struct LabeledHistogram<K> {
+    config: Arc<Config>,
+    histograms: HashMap<K, Histogram>,
+}
+
+impl<K: Eq + Hash> LabeledHistogram<K> {
+    pub fn new(config: Config) -> LabeledHistogram<K> {
+        LabeledHistogram { config: Arc::new(config), histograms: HashMap::new() }
+    }
+
+    pub fn append(&mut self, key: K, value: u64) {
+        let config = &self.config;
+        self.histograms.entry(key)
+            .or_insert_with(|| Histogram::with_shared_config(config.clone(), Instant::now()))
+            .append(value);
+    }
+
+    pub fn get(&self, key: &K) -> Option<&Histogram> {
+        self.histograms.get(key)
+    }
+
+    /// summary by every label recorded
+    pub fn snapshot_all(&self) -> HashMap<&K, Snapshot> {
+        self.histograms.iter().map(|(k, h)| (k, h.snapshot())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labeled() {
+        let mut h = LabeledHistogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        for v in 0..10 {
+            h.append("get", v);
+        }
+        h.append("put", 100);
+        let all = h.snapshot_all();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[&"get"].count, 10);
+        assert_eq!(all[&"put"].count, 1);
+        assert_eq!((all[&"put"].min, all[&"put"].max), (100, 100));
+        assert!(Arc::ptr_eq(&h.get(&"get").unwrap().config, &h.get(&"put").unwrap().config));
+        assert!(h.get(&"delete").is_none());
+    }
+}
@@ -1,6 +1,37 @@
 use std::collections::VecDeque;
 use std::fmt::Display;
-use std::time::Instant;
+use std::ops::{Add, AddAssign};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+mod codec;
+mod count_only;
+mod error;
+mod frozen;
+pub mod global;
+mod heatmap;
+mod json;
+mod labeled;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod recorder;
+mod shared;
+mod sketch;
+mod spec;
+mod swap;
+#[cfg(feature = "tdigest")]
+mod tdigest;
+mod timer;
+
+pub use count_only::CountOnlyHistogram;
+pub use error::HistogramError;
+pub use frozen::FrozenHistogram;
+pub use heatmap::HeatmapData;
+pub use labeled::LabeledHistogram;
+pub use recorder::Recorder;
+pub use shared::SharedHistogram;
+pub use swap::SwapHistogram;
+pub use timer::Timer;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Bucket {
@@ -10,6 +41,21 @@ pub struct Bucket {
     pub scale: Vec<Scale>,
     /// dof this bucket
     pub range: Range,
+    /// largest values ascending, if top N is configured
+    pub top: Vec<u64>,
+    /// every sample is accounted in the percentile scales,
+    /// false if loaded or folded from a bucket without them
+    pub has_percentiles: bool,
+    /// natural logarithms of the samples in fixed point of [`LOG_SCALE`] by the percentile scales,
+    /// if log scale configured
+    pub(crate) log: Vec<Scale>,
+    /// squares of the samples for the variance
+    pub(crate) squares: Scale,
+    /// relative error bins of the samples, if the relative error configured
+    pub(crate) sketch: sketch::Sketch,
+    /// t-digest of the samples, if the t-digest estimator configured
+    #[cfg(feature = "tdigest")]
+    pub(crate) digest: tdigest::TDigest,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -17,44 +63,101 @@ pub struct Scale {
     /// measured aggregated value
     pub sum: u64,
     /// sums power if use
-    pub power: u32,
+    pub power: u64,
     /// counter
-    pub count: u32,
+    pub count: u64,
 }
 
 impl Scale {
-    /// safe sum
+    /// safe sum of the value counted n times
+    #[inline]
+    fn append_n(&mut self, value: u64, n: u32) {
+        let total = value as u128 * n as u128;
+        self.power = self.power.saturating_add((total / u64::MAX as u128) as u64);
+        self.add_sum((total % u64::MAX as u128) as u64);
+        self.count = self.count.saturating_add(n as u64);
+    }
+
+    /// safe sum of the square of the value counted n times, saturated
+    #[inline]
+    fn append_square_n(&mut self, value: u64, n: u32) {
+        let square = (value as u128 * value as u128).saturating_mul(n as u128);
+        self.set_total(self.total().saturating_add(square));
+        self.count = self.count.saturating_add(n as u64);
+    }
+
+    /// safe sum, overflow goes to power
     #[inline]
-    fn append(&mut self, value: u64) {
+    fn add_sum(&mut self, value: u64) {
         let v = u64::MAX - self.sum;
         if value >= v {
-            if self.power < u32::MAX {
-                self.power += 1;
-            }
+            self.power = self.power.saturating_add(1);
             self.sum = value - v;
         } else {
             self.sum += value;
         }
-        if self.count < u32::MAX {
-            self.count += 1;
-        }
+    }
+
+    /// check if append of the value saturate the counters
+    #[inline]
+    fn overflows(&self, value: u64) -> bool {
+        self.count == u64::MAX || (self.power == u64::MAX && value >= u64::MAX - self.sum)
     }
 
     #[inline]
     fn add(&mut self, value: &Self) {
-        if u32::MAX - self.count > value.count {
-            self.count += value.count;
-        }
-        if u32::MAX - self.power > value.power {
-            self.power += value.power;
-        }
-        self.append(value.sum);
+        self.count = self.count.saturating_add(value.count);
+        self.power = self.power.saturating_add(value.power);
+        self.add_sum(value.sum);
+    }
+
+    /// reverse of add, saturated at zero
+    #[inline]
+    fn sub(&mut self, value: &Self) {
+        self.count = self.count.saturating_sub(value.count);
+        self.set_total(self.total().saturating_sub(value.total()));
+    }
+
+    /// same count with the total multiplied by the factor, rounded to nearest
+    fn scaled(&self, factor: f64) -> Scale {
+        let mut r = self.clone();
+        r.set_total((self.total() as f64 * factor).round() as u128);
+        r
+    }
+
+    /// sum with the power
+    #[inline]
+    fn total(&self) -> u128 {
+        self.sum as u128 + u64::MAX as u128 * self.power as u128
+    }
+
+    /// split into the sum and the saturated power
+    #[inline]
+    fn set_total(&mut self, total: u128) {
+        let power = total / u64::MAX as u128;
+        self.sum = (total % u64::MAX as u128) as u64;
+        self.power = u64::try_from(power).unwrap_or(u64::MAX);
     }
 
+    /// average of the aggregated values
     #[inline]
-    fn avg(&self) -> u64 {
-        let power = u64::MAX as u128 * self.power as u128;
-        ((self.sum as u128 + power) / self.count as u128) as u64
+    pub fn avg(&self) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        (self.total() / self.count as u128) as u64
+    }
+
+    /// average of the aggregated values with the rounding, 0 if empty
+    pub fn avg_rounded(&self, rounding: Rounding) -> u64 {
+        match rounding {
+            Rounding::RoundDown => self.avg(),
+            Rounding::RoundNearest if self.count == 0 => 0,
+            Rounding::RoundNearest => {
+                let count = self.count as u128;
+                ((self.total() + count / 2) / count) as u64
+            }
+        }
     }
 
 }
@@ -72,19 +175,80 @@ impl Display for Range {
 }
 
 impl Range {
+    #[inline]
+    fn scaled(&self, factor: f64) -> Range {
+        if self.min_max.0 > self.min_max.1 {
+            return self.clone();
+        }
+        Range { min_max: (scale_value(self.min_max.0, factor), scale_value(self.min_max.1, factor)) }
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min_max.0
+    }
+
+    /// widen to cover the other range, an empty other range is ignored
+    #[inline]
+    pub fn merge(&mut self, other: &Range) {
+        if other.min_max.0 > other.min_max.1 {
+            return;
+        }
+        self.min_max.0 = self.min_max.0.min(other.min_max.0);
+        self.min_max.1 = self.min_max.1.max(other.min_max.1);
+    }
+
+    pub fn max(&self) -> u64 {
+        self.min_max.1
+    }
+
     #[inline]
     fn check(&mut self, value: u64) {
         if self.min_max.0 > value {
             self.min_max.0 = value;
-        } else if self.min_max.1 < value {
+        }
+        if self.min_max.1 < value {
             self.min_max.1 = value
         }
     }
 
+    /// widened to the min width if narrower, around the center or from the side of the tail
+    #[inline]
+    fn floored(&self, tail: Tail, min_width: u64) -> Range {
+        let (min, max) = self.min_max;
+        if min > max || max - min >= min_width {
+            return self.clone();
+        }
+        let extra = min_width - (max - min);
+        let min_max = match tail {
+            Tail::Both => (min.saturating_sub(extra / 2), max.saturating_add(extra - extra / 2)),
+            Tail::Upper => (max.saturating_sub(min_width), max),
+            Tail::Lower => (min, min.saturating_add(min_width)),
+        };
+        Range { min_max }
+    }
+
+    /// the band bounds are rounded in integers of u128 to keep the precision of the wide ranges,
+    /// the skew of -1..=1 shifts the band of both tails toward the max by the share of its cut
     #[inline]
-    fn check_in(&self,  percentile: u8, value: u64) -> bool {
-        let pp = ((self.min_max.1 - self.min_max.0)  as f32 / 200f32 * (100f32 - percentile as f32)).round() as u64;
-        self.min_max.0 + pp  <= value && self.min_max.1 - pp >= value
+    fn check_in(&self, tail: Tail, skew: f32, percentile: u16, value: u64) -> bool {
+        let (min, max) = self.min_max;
+        let width = max.saturating_sub(min) as u128 * 10000u16.saturating_sub(percentile) as u128;
+        let out = ((width + 5000) / 10000) as u64;
+        match tail {
+            Tail::Both => {
+                let pp = ((width + 10000) / 20000) as u64;
+                let (low, high) = if skew == 0f32 {
+                    (pp, pp)
+                } else {
+                    let cut = pp.saturating_mul(2);
+                    let low = ((pp as f64 * (1f64 + skew as f64)).round() as u64).min(cut);
+                    (low, cut - low)
+                };
+                min.saturating_add(low) <= value && max.saturating_sub(high) >= value
+            }
+            Tail::Upper => max.saturating_sub(out) <= value && max >= value,
+            Tail::Lower => min <= value && min.saturating_add(out) >= value,
+        }
     }
 }
 
@@ -97,14 +261,55 @@ impl Default for Range {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Config {
-    /// aggregated percentiles configuration, 10 config max
-    pub(crate) percentiles: Vec<u8>,
+    /// aggregated percentiles configuration in hundredths of a percent, i.e. 9990 is 99.9%, 10 config max
+    pub(crate) percentiles: Vec<u16>,
     /// bucket lifetime
     pub(crate) span_sec: u8,
     /// gauge lifetime
     pub(crate) live_time_sec: u16,
+    /// unit of the measured durations
+    pub(crate) time_unit: TimeUnit,
+    /// track the N largest samples per bucket
+    pub(crate) top_n: Option<usize>,
+    /// samples in band required to report a percentile, the default of 1 keeps the 0 of an empty band
+    pub(crate) min_samples_for_percentile: usize,
+    /// rounding of the averages and quantiles
+    pub(crate) rounding: Rounding,
+    /// keep buckets reached the top band of the highest percentile for this multiple of the live time,
+    /// the memory grows up to the multiple of buckets if the spikes are frequent
+    pub(crate) tail_retention: Option<u8>,
+    /// side of the range a percentile band is taken from
+    pub(crate) tail: Tail,
+    /// values above are invalid, i.e. garbage of a bad instrumentation
+    pub(crate) max_valid_value: Option<u64>,
+    /// record the invalid values as the max valid value instead of dropping
+    pub(crate) clamp_invalid: bool,
+    /// how the percentile values are estimated
+    pub(crate) estimator: PercentileEstimator,
+    /// seconds after start the stats are not reported, 0 to report at once
+    pub(crate) warmup_sec: u16,
+    /// also sum the logarithms of the samples by the percentile scales for the exact geometric mean
+    /// of all and of the bands, an extra scale of 24 bytes for each percentile scale of a bucket
+    pub(crate) log_scale: bool,
+    /// samples the percentile bands are aggregated of
+    pub(crate) window: PercentileWindow,
+    /// count of the recent new lifetime max values to keep, 0 for none
+    pub(crate) extreme_log_size: usize,
+    /// floor of the range width the percentile bands are taken of, so near equal samples
+    /// do not collapse the bands, 0 for the actual range
+    pub(crate) min_range_width: u64,
+    /// -1..=1 shift of the bands of both tails toward the max, 1 is the band of the upper tail,
+    /// 0 for the centered band
+    pub(crate) skew: f32,
+    /// bound of the relative error of [`Histogram::quantile`] in hundredths of a percent, i.e. 100 is 1%,
+    /// the samples are also counted by bins of the relative width per bucket, 0 for none,
+    /// the bins are folded, split and encoded with the buckets
+    pub(crate) relative_error: u16,
+    /// record into a single bucket never evicted, for the min, max and mean of every sample since start
+    /// without the cost of the window, the percentiles are not allowed and their queries fail of no window
+    pub(crate) lifetime_only: bool,
 }
 
 impl Default for Config {
@@ -113,6 +318,105 @@ impl Default for Config {
             percentiles: vec![],
             span_sec: 1,
             live_time_sec: 120,
+            time_unit: TimeUnit::default(),
+            top_n: None,
+            min_samples_for_percentile: 1,
+            rounding: Rounding::default(),
+            tail_retention: None,
+            tail: Tail::default(),
+            max_valid_value: None,
+            clamp_invalid: false,
+            estimator: PercentileEstimator::default(),
+            warmup_sec: 0,
+            log_scale: false,
+            window: PercentileWindow::default(),
+            extreme_log_size: 0,
+            min_range_width: 0,
+            skew: 0f32,
+            relative_error: 0,
+            lifetime_only: false,
+        }
+    }
+}
+
+/// unit of the recorded durations
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TimeUnit {
+    Nanos,
+    #[default]
+    Micros,
+    Millis,
+}
+
+/// rounding of the integer results
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Rounding {
+    /// truncate, biased low
+    #[default]
+    RoundDown,
+    /// round half up
+    RoundNearest,
+}
+
+/// slice of the range by a percentile
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Tail {
+    /// band of the percentile width centered in the range
+    #[default]
+    Both,
+    /// top of the range beyond the percentile, i.e. the slowest 5% for p95
+    Upper,
+    /// bottom of the range beyond the percentile
+    Lower,
+}
+
+/// samples the percentile bands are aggregated of
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PercentileWindow {
+    /// the live buckets only, accurate to the window but a spike is gone once the buckets evicted
+    #[default]
+    Live,
+    /// every sample since start or [`Histogram::reset_lifetime`], an evicted spike is kept
+    /// but the values are stale of the old samples, for the band estimator only
+    Lifetime,
+}
+
+/// estimation of the percentile values, non exhaustive as the variants of the features are additive
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub enum PercentileEstimator {
+    /// average of the samples within the percentile band of the range, see [`Tail`],
+    /// aggregated on append so a read is O(1), crude for skewed data
+    #[default]
+    RangeBand,
+    /// value the percentile share of samples is below, interpolated over the bucket counts
+    /// uniform within each bucket range, see [`Histogram::quantile`], a read is a search over the buckets
+    WeightedBuckets,
+    /// t-digest per bucket of the appended samples, accurate at the tails, a read merges the digests,
+    /// about 50 centroids a bucket to keep, the digests are folded and encoded with the buckets
+    #[cfg(feature = "tdigest")]
+    TDigest,
+}
+
+impl TimeUnit {
+    /// duration as the count of units, saturated on overflow
+    #[inline]
+    pub fn convert(&self, d: Duration) -> u64 {
+        let v = match self {
+            TimeUnit::Nanos => d.as_nanos(),
+            TimeUnit::Micros => d.as_micros(),
+            TimeUnit::Millis => d.as_millis(),
+        };
+        if v >= u64::MAX as u128 { u64::MAX } else { v as u64 }
+    }
+
+    /// count of units as the duration
+    #[inline]
+    pub fn duration(&self, v: u64) -> Duration {
+        match self {
+            TimeUnit::Nanos => Duration::from_nanos(v),
+            TimeUnit::Micros => Duration::from_micros(v),
+            TimeUnit::Millis => Duration::from_millis(v),
         }
     }
 }
@@ -122,12 +426,17 @@ impl Config {
     pub fn validate(self) -> Result<(), String> {
         let mut msg = String::new();
         for p in &self.percentiles {
-            Config::append(&mut msg, *p >= 100, "'percentile' mut be less than 100%");
-            Config::append(&mut msg, *p <= 50, "'percentile' mut be great than 50%");
+            Config::append(&mut msg, *p >= 10000, "'percentile' mut be less than 100%");
+            Config::append(&mut msg, *p <= 5000, "'percentile' mut be great than 50%");
         }
         Config::append(&mut msg, self.span_sec == 0, "'span' mut be great than 0");
         Config::append(&mut msg, self.live_time_sec < self.span_sec as u16 + 1u16, "'live_time_sec' mut be great than 'span'");
-        if msg.len() > 0 {
+        Config::append(&mut msg, self.tail_retention == Some(0), "'tail_retention' mut be great than 0");
+        Config::append(&mut msg, self.relative_error >= 10000, "'relative_error' mut be less than 100%");
+        Config::append(&mut msg, self.extreme_log_size as u64 > u32::MAX as u64, "'extremes' mut be less than 2^32");
+        Config::append(&mut msg, !(-1f32..=1f32).contains(&self.skew), "'skew' mut be within -1 and 1");
+        Config::append(&mut msg, self.lifetime_only && !self.percentiles.is_empty(), "'percentile' mut be none if 'lifetime_only'");
+        if !msg.is_empty() {
             Err(msg)
         } else {
             Ok(())
@@ -137,38 +446,66 @@ impl Config {
     #[inline]
     fn append(msg: &mut String, cnd: bool, err: &str) {
         if cnd {
-            if msg.len() > 0 {
+            if !msg.is_empty() {
                 msg.push_str(", ");
             }
             msg.push_str(err);
         }
     }
 
-    pub fn find(&self, percentile: u8) -> Result<usize, String> {
-        if percentile > 10 {
-            let mut idx = 1;
-            let mut found = false;
-            for p in &self.percentiles {
-                if *p == percentile {
-                    found = true;
-                    break;
-                }
-                idx += 1;
-            }
-            if found {
-                Ok(idx)
-            } else {
-                Err(format!("cant find {}% of {}", percentile, self.percentiles.len()))
-            }
-        } else if self.percentiles.len() > 0
+    /// scale index of a whole percentile, or of the configured percentile by index if up to 10
+    pub fn find(&self, percentile: u8) -> Result<usize, HistogramError> {
+        if self.lifetime_only {
+            Err(HistogramError::NoWindow)
+        } else if percentile > 10 {
+            self.find_hundredths(percentile as u16 * 100)
+        } else if !self.percentiles.is_empty()
             && self.percentiles.len() > percentile as usize {
             Ok(percentile as usize + 1)
         } else {
-            Err(format!("cant find #{} of {}", percentile, self.percentiles.len()))
+            Err(HistogramError::UnknownPercentile(format!("cant find #{} of {}", percentile, self.percentiles.len())))
+        }
+    }
+
+    /// scale index of a percentile given in hundredths of a percent, i.e. 9990 for 99.9%
+    pub fn find_hundredths(&self, percentile: u16) -> Result<usize, HistogramError> {
+        if self.lifetime_only {
+            return Err(HistogramError::NoWindow);
+        }
+        let mut idx = 1;
+        let mut found = false;
+        for p in &self.percentiles {
+            if *p == percentile {
+                found = true;
+                break;
+            }
+            idx += 1;
+        }
+        if found {
+            Ok(idx)
+        } else {
+            Err(HistogramError::UnknownPercentile(format!("cant find {}.{:02}% of {}",
+                percentile / 100, percentile % 100, self.percentiles.len())))
         }
     }
 }
 
+/// config of about `target_buckets` buckets over the window, a bucket is at least 2 seconds,
+/// so a window shorter than the double target yields less buckets, as does a window longer than
+/// the 256 seconds buckets of the target, the window is capped at u16 seconds
+pub fn suggest_config(target_buckets: usize, window: Duration) -> Config {
+    let window = window.as_secs().clamp(1, u16::MAX as u64);
+    // a bucket takes the samples of the begin second and of the span after
+    let width = window.div_ceil(target_buckets.max(1) as u64).clamp(2, u8::MAX as u64 + 1);
+    let span = width - 1;
+    Config {
+        span_sec: span as u8,
+        // the newest bucket is filling
+        live_time_sec: window.saturating_sub(width).clamp(span + 1, u16::MAX as u64) as u16,
+        ..Default::default()
+    }
+}
+
 impl Bucket {
     fn new(time: u32) -> Self {
         Bucket { time,
@@ -178,170 +515,3746 @@ impl Bucket {
                 count: 0,
             }],
             range: Default::default(),
+            top: vec![],
+            has_percentiles: true,
+            log: vec![],
+            squares: Scale { sum: 0, power: 0, count: 0 },
+            sketch: Default::default(),
+            #[cfg(feature = "tdigest")]
+            digest: Default::default(),
+        }
+    }
+
+    /// empty for reuse at the time keeping the allocations
+    fn reset(&mut self, time: u32) {
+        self.time = time;
+        self.scale.truncate(1);
+        self.scale[0] = Scale { sum: 0, power: 0, count: 0 };
+        self.range = Default::default();
+        self.top.clear();
+        self.has_percentiles = true;
+        self.log.clear();
+        self.squares = Scale { sum: 0, power: 0, count: 0 };
+        self.sketch.clear();
+        #[cfg(feature = "tdigest")]
+        self.digest.clear();
+    }
+
+    /// add scales, range, top values, relative error bins and t-digest of the other bucket
+    fn fold(&mut self, other: &Bucket, top_n: Option<usize>) {
+        for (i, s) in other.scale.iter().enumerate() {
+            if self.scale.len() <= i {
+                self.scale.push(Scale { sum: 0, power: 0, count: 0 });
+            }
+            self.scale[i].add(s);
+        }
+        add_scales(&mut self.log, &other.log);
+        self.squares.set_total(self.squares.total().saturating_add(other.squares.total()));
+        self.squares.count = self.squares.count.saturating_add(other.squares.count);
+        self.sketch.fold(&other.sketch);
+        #[cfg(feature = "tdigest")]
+        self.digest.fold(&other.digest);
+        if other.scale[0].count > 0 {
+            self.range.merge(&other.range);
+            self.has_percentiles &= other.has_percentiles;
+        }
+        if let Some(n) = top_n {
+            for v in &other.top {
+                self.push_top(*v, n);
+            }
+        }
+    }
+
+    /// drop the percentiles flag if loaded or merged short of the percentile scales,
+    /// an empty bucket is not yet grown to them
+    pub(crate) fn check_percentiles(&mut self, percentiles: usize) {
+        self.has_percentiles &= self.scale.len() == percentiles + 1 || self.scale[0].count == 0;
+    }
+
+    /// keep the value if one of the `n` largest
+    #[inline]
+    fn push_top(&mut self, value: u64, n: usize) {
+        if self.top.len() >= n {
+            if n == 0 || self.top[0] >= value {
+                return;
+            }
+            self.top.remove(0);
+        }
+        let idx = self.top.partition_point(|v| *v < value);
+        self.top.insert(idx, value);
+    }
+
+    /// natural logarithms of the samples in fixed point of [`LOG_SCALE`] by the percentile scales,
+    /// empty if no log scale configured
+    pub fn log_scales(&self) -> &[Scale] {
+        &self.log
+    }
+
+    /// squares of the samples, the total is saturated
+    pub fn squares(&self) -> &Scale {
+        &self.squares
+    }
+
+    /// sample variance of the bucket, none if less than two samples
+    pub fn variance(&self) -> Option<f64> {
+        let n = self.scale[0].count;
+        if n < 2 {
+            return None;
+        }
+        let sum = self.scale[0].total() as f64;
+        let var = (self.squares.total() as f64 - sum * sum / n as f64) / (n - 1) as f64;
+        Some(var.max(0f64))
+    }
+
+    /// sample standard deviation of the bucket, none if less than two samples
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// estimated count of samples above the threshold, uniform within the range
+    #[inline]
+    fn count_above(&self, threshold: u64) -> f64 {
+        let count = self.scale[0].count as f64;
+        let (min, max) = self.range.min_max;
+        if count == 0f64 || threshold >= max {
+            0f64
+        } else if threshold < min {
+            count
+        } else {
+            count * (max - threshold) as f64 / (max - min) as f64
+        }
+    }
+
+    /// estimated count of samples below the threshold, uniform within the range
+    #[inline]
+    fn count_below(&self, threshold: u64) -> f64 {
+        let count = self.scale[0].count as f64;
+        let (min, max) = self.range.min_max;
+        if count == 0f64 || threshold <= min {
+            0f64
+        } else if threshold > max {
+            count
+        } else {
+            count * (threshold - min) as f64 / (max - min) as f64
         }
     }
 }
 
+/// growth in percent a mean or a percentile is marked a regression above by [`diff_report`]
+pub const REGRESSION_PERCENT: u64 = 10;
+
+/// lines of `name: baseline -> current (+N%)` of the count, mean and the percentiles of both,
+/// i.e. `p95: 48 -> 62 (+29%) regression` of the growth above [`REGRESSION_PERCENT`]
+pub fn diff_report(baseline: &Snapshot, current: &Snapshot) -> String {
+    let line = |name: &str, base: u64, cur: u64, check: bool| {
+        let change = match base {
+            0 if cur == 0 => "+0%".to_string(),
+            0 => "new".to_string(),
+            _ => format!("{:+}%", ((cur as f64 - base as f64) * 100f64 / base as f64).round() as i64),
+        };
+        let regression = check && cur > base && (cur - base) as u128 * 100 > base as u128 * REGRESSION_PERCENT as u128;
+        format!("{}: {} -> {} ({}){}", name, base, cur, change, if regression { " regression" } else { "" })
+    };
+    let mut lines = vec![
+        line("count", baseline.count, current.count, false),
+        line("mean", baseline.mean, current.mean, true),
+    ];
+    for (p, cur) in &current.percentiles {
+        if let Some((_, base)) = baseline.percentiles.iter().find(|(bp, _)| bp == p) {
+            lines.push(line(&percentile_label(*p), *base, *cur, true));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Pearson correlation of the bucket means of both histograms begun at the same wall-clock second,
+/// the bucket times of `b` shifted by the difference of the epochs rounded to seconds,
+/// none if less than two aligned buckets or a constant series
+pub fn correlate(a: &Histogram, b: &Histogram) -> Option<f64> {
+    let shift = match b.epoch.duration_since(a.epoch) {
+        Ok(d) => d.as_secs_f64().round() as i64,
+        Err(e) => -(e.duration().as_secs_f64().round() as i64),
+    };
+    let mean = |x: &Bucket| x.scale[0].avg() as f64;
+    let pairs: Vec<(f64, f64)> = a.buckets.iter()
+        .filter(|x| x.scale[0].count > 0)
+        .filter_map(|x| b.buckets.iter()
+            .find(|y| y.scale[0].count > 0 && y.time as i64 + shift == x.time as i64)
+            .map(|y| (mean(x), mean(y))))
+        .collect();
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let (mx, my) = pairs.iter().fold((0f64, 0f64), |m, (x, y)| (m.0 + x / n, m.1 + y / n));
+    let (mut cov, mut vx, mut vy) = (0f64, 0f64, 0f64);
+    for (x, y) in &pairs {
+        cov += (x - mx) * (y - my);
+        vx += (x - mx) * (x - mx);
+        vy += (y - my) * (y - my);
+    }
+    if vx == 0f64 || vy == 0f64 {
+        return None;
+    }
+    Some(cov / (vx * vy).sqrt())
+}
+
+/// `p99.9` of the percentile in hundredths
+fn percentile_label(percentile: u16) -> String {
+    let fraction = format!("{:02}", percentile % 100);
+    match fraction.trim_end_matches('0') {
+        "" => format!("p{}", percentile / 100),
+        f => format!("p{}.{}", percentile / 100, f),
+    }
+}
+
+/// coverage of the expected band count damped for the small samples,
+/// a one-sided band is expected to hold the share beyond the percentile
+#[inline]
+fn confidence(total: u64, n: u64, percentile: u16, tail: Tail) -> f64 {
+    let n = n as f64;
+    let share = match tail {
+        Tail::Both => percentile,
+        Tail::Upper | Tail::Lower => 10000 - percentile,
+    };
+    let expected = total as f64 * share as f64 / 10000f64;
+    (n / expected).min(1f64) * (1f64 - 1f64 / (n + 1f64).sqrt())
+}
+
+/// value multiplied by the factor, rounded to nearest and saturated
+#[inline]
+fn scale_value(value: u64, factor: f64) -> u64 {
+    (value as f64 * factor).round() as u64
+}
+
+/// summary of the retained samples
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub count: u64,
+    pub mean: u64,
+    pub min: u64,
+    pub max: u64,
+    pub median: u64,
+    /// averages by the configured percentiles in hundredths
+    pub percentiles: Vec<(u16, u64)>,
+}
+
+/// summary of a bucket, min and max are 0 if empty
+#[derive(Clone, Debug, PartialEq)]
+pub struct BucketSnapshot {
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    pub mean: u64,
+}
+
+/// aggregate of a configured percentile
+#[derive(Clone, Debug, PartialEq)]
+pub struct PercentileResult {
+    /// in hundredths of a percent as configured, i.e. 9990 is 99.9%
+    pub percentile: u16,
+    /// average of the samples within the band, 0 while warming up
+    pub average: u64,
+    /// samples within the band
+    pub count: u64,
+    /// see [`Histogram::percentile_confidence`], 0 if empty
+    pub confidence: f64,
+    /// times of the buckets not accounted for lack of the percentile scales
+    pub excluded: Vec<u32>,
+}
+
+/// elapsed seconds to roll the timeline forward
+const ROLL_SEC: u64 = u32::MAX as u64 / 2;
+
+/// fixed point of the log scale, the logarithm of the `u64::MAX` fits 2^26 of a sample
+pub const LOG_SCALE: f64 = (1u64 << 20) as f64;
+
+/// rounded to nearest of the significant figures, saturated
+fn round_sig(value: u64, sig_figs: u8) -> u64 {
+    let digits = value.checked_ilog10().map_or(1, |d| d + 1);
+    if sig_figs == 0 || digits <= sig_figs as u32 {
+        return value;
+    }
+    let unit = 10u64.pow(digits - sig_figs as u32);
+    let rounded = (value as u128 + unit as u128 / 2) / unit as u128 * unit as u128;
+    rounded.min(u64::MAX as u128) as u64
+}
+
+/// add the scales by index, the missing appended
+fn add_scales(to: &mut Vec<Scale>, scales: &[Scale]) {
+    if to.len() < scales.len() {
+        to.resize(scales.len(), Scale { sum: 0, power: 0, count: 0 });
+    }
+    for (t, s) in to.iter_mut().zip(scales) {
+        t.add(s);
+    }
+}
+
+/// log scale fixed point of the value, zero counted as one
+#[inline]
+fn log_fixed(value: u64) -> u64 {
+    ((value.max(1) as f64).ln() * LOG_SCALE).round() as u64
+}
+
 /// A histogram that uses plain 64bit counters for each bucket.
 #[derive(Clone, Debug)]
 pub struct Histogram {
-    pub(crate) config: Config,
+    /// shared by clones
+    pub(crate) config: Arc<Config>,
     pub(crate) start: Instant,
+    /// wall-clock time of the start
+    pub(crate) epoch: SystemTime,
     pub(crate) buckets: VecDeque<Bucket>,
     /// overall range of buckets, modified on evict
     pub(crate) range: Range,
     /// overall range lifetime
     pub(crate) range_lifetime: Range,
+    /// aggregated scales of retained buckets by percentile index, 0 is all samples,
+    /// added on append and subtracted on evict
+    pub(crate) totals: Vec<Scale>,
+    /// samples above the max valid value, dropped or clamped
+    pub(crate) rejected: u64,
+    /// all samples and per percentile scales never evicted
+    pub(crate) lifetime: Vec<Scale>,
+    /// fixed count of buckets allocated once, the oldest is reused for a new one
+    pub(crate) ring: Option<usize>,
+    /// `(time, value)` of the recent new lifetime max values, oldest first
+    pub(crate) extremes: VecDeque<(u32, u64)>,
+    /// current instant, [`Instant::now`] unless injected by [`Histogram::with_clock`]
+    pub(crate) clock: fn() -> Instant,
 }
 
 impl Histogram {
     pub fn new(config: Config) -> Histogram {
+        Histogram::with_start(config, Instant::now())
+    }
+
+    /// create with a given timeline origin, i.e. to continue the timeline of a restored histogram,
+    /// the `start` must not be in the future relative to bucket times
+    pub fn with_start(config: Config, start: Instant) -> Histogram {
+        Histogram::with_shared_config(Arc::new(config), start)
+    }
+
+    /// create with the clock of the current instant, i.e. a test clock, started at its instant
+    pub fn with_clock(config: Config, clock: fn() -> Instant) -> Histogram {
+        let mut h = Histogram::with_start(config, clock());
+        h.clock = clock;
+        h
+    }
+
+    /// create of the config shared with other histograms
+    pub(crate) fn with_shared_config(config: Arc<Config>, start: Instant) -> Histogram {
         Histogram {
             config,
-            start: Instant::now(),
+            start,
+            epoch: SystemTime::now().checked_sub(start.elapsed()).unwrap_or(SystemTime::UNIX_EPOCH),
             buckets: Default::default(),
             range: Default::default(),
             range_lifetime: Default::default(),
+            totals: vec![],
+            rejected: 0,
+            lifetime: vec![],
+            ring: None,
+            extremes: VecDeque::new(),
+            clock: Instant::now,
         }
     }
 
-    pub fn append(&mut self, value: u64) {
-         let time = self.start.elapsed().as_secs();
-         let time = if time >= u32::MAX as u64 { u32::MAX } else { time  as u32};
-         if self.buckets.len() == 0  || time - &self.buckets.front().unwrap().time > self.config.span_sec as u32 {
-             self.buckets.push_front(Bucket::new(time))
-         }
-         self.range.check(value);
-         self.range_lifetime.check(value);
-         let b = self.buckets.front_mut().unwrap();
-         b.scale.get_mut(0).unwrap().append(value);
-         if b.range.min_max.0 > value {
-             b.range.min_max.0 = value;
-         } else if b.range.min_max.1 < value {
-             b.range.min_max.1 = value
-         }
-
-         for percentile_id in 1..self.config.percentiles.len()+1 {
-             if b.scale.len() <= percentile_id {
-                 b.scale.push(Scale { sum: 0, power: 0, count: 0 });
-             }
+    /// create with the buckets of the live time allocated once, appends never allocate after,
+    /// a new bucket reuses the oldest one if all taken, i.e. if no live time configured
+    /// so the memory is bounded by the `live_time_sec / span_sec + 2` buckets, or a single bucket
+    pub fn with_ring_buffer(config: Config) -> Histogram {
+        let cap = if config.live_time_sec == 0 { 1 } else { config.live_time_sec as usize / config.span_sec as usize + 2 };
+        let mut h = Histogram::new(config);
+        h.buckets.reserve_exact(cap);
+        h.totals.resize(h.config.percentiles.len() + 1, Scale { sum: 0, power: 0, count: 0 });
+        h.lifetime = h.totals.clone();
+        h.ring = Some(cap);
+        h
+    }
 
-             if self.range.check_in(self.config.percentiles[percentile_id - 1], value) {
-                 b.scale[percentile_id].append(value);
-             }
-         }
+    /// since start of the clock
+    #[inline]
+    fn elapsed(&self) -> Duration {
+        (self.clock)().saturating_duration_since(self.start)
+    }
 
-         // check to evict
-         if self.buckets.len() > 1 && self.config.live_time_sec > 0
-             && self.buckets.back().unwrap().time > self.config.live_time_sec as u32 {
-             let b = &self.buckets.pop_back().unwrap();
-             if b.range.min_max.0 < self.range.min_max.0 || b.range.min_max.1 > self.range.min_max.1 {
-
-                 // modify range after evict
-                 let mut r = Range::default();
-                 // lookup new range
-                 for x in &self.buckets {
-                     if x.range.min_max.0 < r.min_max.0 {
-                         r.min_max.0 = x.range.min_max.0;
-                     }
-                     if x.range.min_max.1 > r.min_max.1 {
-                         r.min_max.1 = x.range.min_max.1;
-                     }
-                 }
-                 self.range = r;
-             }
-         }
+    /// seconds from start
+    #[inline]
+    fn now(&self) -> u32 {
+        let time = self.elapsed().as_secs();
+        if time >= u32::MAX as u64 { u32::MAX } else { time  as u32}
+    }
 
+    /// seconds from start, rolling the start forward once the half of u32 seconds (~68 years) passed,
+    /// so the bucket times never saturate, the bucket ages are kept for the longest possible live time
+    /// while older buckets (only retained if no live time configured) are saturated at the new start
+    #[inline]
+    fn tick(&mut self) -> u32 {
+        let time = self.elapsed().as_secs();
+        if time >= ROLL_SEC {
+            self.roll((time - u16::MAX as u64) as u32);
+        }
+        self.now()
     }
 
-    pub fn median(&self) -> u64 {
-        let min = self.range.min_max.0;
-        min + (self.range.min_max.1 - min) / 2
+    /// move the start forward keeping the bucket ages
+    fn roll(&mut self, secs: u32) {
+        let d = Duration::from_secs(secs as u64);
+        self.start += d;
+        self.epoch += d;
+        for b in self.buckets.iter_mut() {
+            b.time = b.time.saturating_sub(secs);
+        }
     }
 
-    pub fn median_lt(&self) -> u64 {
-        let min = self.range_lifetime.min_max.0;
-        min + (self.range_lifetime.min_max.1 - min) / 2
+    pub fn append(&mut self, value: u64) {
+        let time = self.tick();
+        self.record(time, value);
     }
 
-    ///
-    pub fn average(&self) -> u64 {
-        let mut r = Scale { sum: 0, power: 0, count: 0 };
-        for b in &self.buckets {
-            r.add(&b.scale[0])
-        }
-        r.avg()
+    /// append the value counted n times
+    pub fn append_n(&mut self, value: u64, n: u32) {
+        let time = self.tick();
+        self.record_n(time, value, n);
     }
 
-    ///
-    pub fn average_p(&self, percentile: u8) -> Result<u64, String> {
-        let pid = self.config.find(percentile)?;
-        let mut r = Scale { sum: 0, power: 0, count: 0 };
-        for b in &self.buckets {
-            r.add(&b.scale[pid])
+    /// load `(value, count)` pairs as recorded now
+    pub fn from_recorded_values(config: Config, values: &[(u64, u64)]) -> Histogram {
+        let mut h = Histogram::new(config);
+        let time = h.now();
+        for (value, count) in values {
+            let mut count = *count;
+            while count > 0 {
+                let n = count.min(u32::MAX as u64);
+                h.record_n(time, *value, n as u32);
+                count -= n;
+            }
         }
-        Ok(r.avg())
+        h
     }
 
-    pub fn buckets(&self) -> usize {
-        self.buckets.len()
+    /// lossy `(value, count)` pairs ascending by value, the value is the middle of a bucket range
+    pub fn to_recorded_values(&self) -> Vec<(u64, u64)> {
+        let mut values: Vec<(u64, u64)> = self.buckets.iter()
+            .filter(|b| b.scale[0].count > 0)
+            .map(|b| {
+                let (min, max) = b.range.min_max;
+                (min + (max - min) / 2, b.scale[0].count)
+            })
+            .collect();
+        values.sort_unstable();
+        values.dedup_by(|next, prev| {
+            if next.0 == prev.0 {
+                prev.1 += next.1;
+                true
+            } else {
+                false
+            }
+        });
+        values
     }
 
-    pub fn sample_count(&self) -> usize {
-        let mut s = 0usize;
-        for b in &self.buckets {
-            s += b.scale[0].count as usize;
+    /// `n` synthetic samples oldest first, shared by the bucket counts and spread evenly within
+    /// each bucket range, so the mean approximates [`Histogram::mean`] for uniform buckets
+    pub fn resample_uniform(&self, n: usize) -> Vec<u64> {
+        let total = self.sample_count();
+        if total == 0 {
+            return vec![];
         }
-        s
+        let row: Vec<f64> = self.buckets.iter().rev()
+            .map(|b| b.scale[0].count as f64 * n as f64 / total as f64)
+            .collect();
+        let shares = heatmap::round_row(&row, n as u64);
+        let mut samples = Vec::with_capacity(n);
+        for (b, k) in self.buckets.iter().rev().zip(shares) {
+            let (min, max) = b.range.min_max;
+            for i in 0..k {
+                samples.push(min + ((max - min) as f64 * (i as f64 + 0.5) / k as f64) as u64);
+            }
+        }
+        samples
     }
 
-    pub fn sample_count_p(&self, percentile: u8) -> Result<usize, String> {
-        let pid = self.config.find(percentile)?;
-        let mut s = 0usize;
-        for b in &self.buckets {
-            s += b.scale[pid].count as usize;
+    /// append every value
+    pub fn extend_from<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        for value in iter {
+            self.append(value);
         }
-        Ok(s)
     }
 
-}
+    /// measure the wall-clock duration of the closure in the configured time unit and append it
+    pub fn time<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let r = f();
+        self.append_duration(started.elapsed());
+        r
+    }
 
+    /// append the duration in the configured time unit
+    pub fn append_duration(&mut self, d: Duration) {
+        self.append(self.config.time_unit.convert(d));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// append or fail if any of the affected counters would saturate, leaving the histogram unchanged
+    pub fn try_append(&mut self, value: u64) -> Result<(), HistogramError> {
+        let time = self.tick();
+        // a rejected value is only counted as rejected
+        if let Some(value) = self.clamped(value) {
+            let mut range = self.range.clone();
+            range.check(value);
+            let range = range.floored(self.config.tail, self.config.min_range_width);
+            let bucket = self.slot_of(time).map(|idx| &self.buckets[idx]);
+            // the scale of the bucket, the totals and the never evicted lifetime
+            let overflows = |i: usize| bucket.and_then(|b| b.scale.get(i)).into_iter()
+                .chain(self.totals.get(i))
+                .chain(self.lifetime.get(i))
+                .any(|s| s.overflows(value));
+            if overflows(0) {
+                return Err(HistogramError::Overflow);
+            }
+            if bucket.is_none_or(|b| b.has_percentiles) {
+                for (i, p) in self.config.percentiles.iter().enumerate() {
+                    if overflows(i + 1) && range.check_in(self.config.tail, self.config.skew, *p, value) {
+                        return Err(HistogramError::Overflow);
+                    }
+                }
+            }
+        }
+        self.record(time, value);
+        Ok(())
+    }
 
-    #[test]
-    fn test() {
-        // let power = u64::MAX as u128 * u32::MAX as u128;
-        // assert!(power > u64::MAX as u128);
-        let mut h = Histogram::new(Config::default());
-        h.append(0);
-        assert_eq!(h.median(), 0);
-        h.append(2);
-        assert_eq!(h.median(), 1);
+    /// append the sample measured at `time` seconds from start,
+    /// the sample is dropped if older than the live time of the newest bucket
+    pub fn append_at(&mut self, time: u32, value: u64) {
+        self.record(time, value);
     }
 
-    #[test]
-    fn test_p() {
-        let mut h = Histogram::new(Config {
-            percentiles: vec![95],
-            span_sec: 1,
-            live_time_sec: 100,
-        });
-        h.append(0);
-        h.append(100);
-        assert_eq!(h.median(), 50);
-        for x in 1..101 {
-            h.append(x);
+    /// append the sample measured at the wall-clock time,
+    /// a time before the epoch is clamped to the epoch, i.e. recorded as of start
+    pub fn append_at_system(&mut self, when: SystemTime, value: u64) {
+        let time = when.duration_since(self.epoch).map(|d| d.as_secs()).unwrap_or(0);
+        let time = if time >= u32::MAX as u64 { u32::MAX } else { time as u32 };
+        self.append_at(time, value);
+    }
+
+    /// wall-clock time of the start
+    pub fn epoch(&self) -> SystemTime {
+        self.epoch
+    }
+
+    /// index of the existing bucket [`Histogram::slot`] records the time into,
+    /// none if a new bucket is to insert or the time is out of the live time
+    fn slot_of(&self, time: u32) -> Option<usize> {
+        let newest = self.buckets.front()?.time;
+        let span = self.config.span_sec as u32;
+        if self.config.lifetime_only {
+            return Some(0);
         }
-        assert_eq!(h.median(), 50);
-        assert_eq!(h.average(), 50);
-        assert_eq!(h.average_p(95).unwrap(), 48);
-        assert_eq!(h.average_p(0 /* by index */).unwrap(), 48);
-        assert_eq!(h.sample_count(), 102);
-        assert_eq!(h.sample_count_p(95).unwrap(), 96);
+        if time >= newest {
+            return (time - newest <= span).then_some(0);
+        }
+        if self.config.live_time_sec > 0 && newest - time > self.config.live_time_sec as u32 {
+            return None;
+        }
+        self.buckets.iter().position(|b| b.time <= time)
+            .filter(|i| time - self.buckets[*i].time <= span)
+    }
 
+    /// index of the bucket for the time, inserted if missing, none if out of the live time
+    fn slot(&mut self, time: u32) -> Option<usize> {
+        if self.config.lifetime_only {
+            if self.buckets.is_empty() {
+                self.buckets.push_front(Bucket::new(0));
+            }
+            return Some(0);
+        }
+        let span = self.config.span_sec as u32;
+        let newest = match self.buckets.front() {
+            Some(b) => b.time,
+            None => {
+                self.buckets.push_front(Bucket::new(time));
+                return Some(0);
+            }
+        };
+        if time >= newest {
+            if time - newest > span {
+                let b = self.new_bucket(time)?;
+                self.buckets.push_front(b);
+            }
+            return Some(0);
+        }
+        if self.config.live_time_sec > 0 && newest - time > self.config.live_time_sec as u32 {
+            return None;
+        }
+        match self.buckets.iter().position(|b| b.time <= time) {
+            Some(i) if time - self.buckets[i].time <= span => Some(i),
+            Some(_) => {
+                let b = self.new_bucket(time)?;
+                let i = self.buckets.iter().position(|b| b.time <= time).unwrap_or(self.buckets.len());
+                self.buckets.insert(i, b);
+                Some(i)
+            }
+            None => {
+                let b = self.new_bucket(time)?;
+                self.buckets.push_back(b);
+                Some(self.buckets.len() - 1)
+            }
+        }
+    }
+
+    /// empty bucket for the time, the oldest one reused if the ring is full,
+    /// none if the time is older than the oldest bucket of the full ring
+    fn new_bucket(&mut self, time: u32) -> Option<Bucket> {
+        match self.ring {
+            Some(cap) if self.buckets.len() >= cap => {
+                if self.buckets.back().is_some_and(|b| b.time > time) {
+                    return None;
+                }
+                let mut b = self.buckets.pop_back().unwrap();
+                if self.forget(&b) {
+                    self.range = self.buckets_range();
+                }
+                b.reset(time);
+                Some(b)
+            }
+            Some(_) => {
+                let mut b = Bucket::new(time);
+                b.scale.reserve_exact(self.config.percentiles.len());
+                b.top.reserve_exact(self.config.top_n.unwrap_or(0));
+                Some(b)
+            }
+            None => Some(Bucket::new(time)),
+        }
+    }
+
+    /// subtract the removed bucket from the totals, true if the range is to rescan
+    #[inline]
+    fn forget(&mut self, b: &Bucket) -> bool {
+        let scales = if b.has_percentiles { b.scale.len() } else { 1 };
+        for (t, s) in self.totals.iter_mut().zip(&b.scale[..scales]) {
+            t.sub(s);
+        }
+        b.range.min_max.0 <= self.range.min_max.0 || b.range.min_max.1 >= self.range.min_max.1
+    }
+
+    fn record(&mut self, time: u32, value: u64) {
+        self.record_n(time, value, 1);
+    }
+
+    /// record the value counted n times
+    fn record_n(&mut self, time: u32, value: u64, n: u32) {
+         if n == 0 {
+             return;
+         }
+         let Some(value) = self.valid(value, n) else {
+             return;
+         };
+         let Some(idx) = self.slot(time) else {
+             return;
+         };
+         self.record_in(idx, time, value, n);
+         let newest = self.buckets.front().unwrap().time;
+         self.evict(newest);
+    }
+
+    /// the value to record, clamped or none if above the max valid value
+    #[inline]
+    fn clamped(&self, value: u64) -> Option<u64> {
+        match self.config.max_valid_value {
+            Some(max) if value > max => self.config.clamp_invalid.then_some(max),
+            _ => Some(value),
+        }
+    }
+
+    /// [`Histogram::clamped`] counting the values above the max valid value as rejected
+    #[inline]
+    fn valid(&mut self, value: u64, n: u32) -> Option<u64> {
+        if self.config.max_valid_value.is_some_and(|max| value > max) {
+            self.rejected = self.rejected.saturating_add(n as u64);
+        }
+        self.clamped(value)
+    }
+
+    /// 0..1 share of the buckets retained of the most the config keeps, the ring capacity
+    /// or `live_time_sec / span_sec + 2` as of the tail retention, 0 if unbounded by no live time,
+    /// i.e. to back off sampling near 1
+    pub fn memory_pressure(&self) -> f64 {
+        let max = match self.ring {
+            Some(cap) => cap,
+            None if self.config.live_time_sec == 0 => return 0f64,
+            None => {
+                let live = self.config.live_time_sec as usize * self.config.tail_retention.unwrap_or(1) as usize;
+                live / self.config.span_sec as usize + 2
+            }
+        };
+        (self.buckets.len() as f64 / max as f64).min(1f64)
+    }
+
+    /// release the memory the buckets had grown to, i.e. after a burst evicted, the percentile scales
+    /// of the buckets without the percentiles dropped, reallocates so not for the hot path,
+    /// the ring keeps its capacity
+    pub fn shrink_to_fit(&mut self) {
+        if self.ring.is_none() {
+            self.buckets.shrink_to_fit();
+            for b in self.buckets.iter_mut() {
+                if !b.has_percentiles {
+                    b.scale.truncate(1);
+                    b.log.truncate(1);
+                }
+                b.log.shrink_to_fit();
+                b.scale.shrink_to_fit();
+                b.top.shrink_to_fit();
+                #[cfg(feature = "tdigest")]
+                b.digest.shrink_to_fit();
+            }
+        }
+        self.extremes.shrink_to_fit();
+    }
+
+    /// count of the samples above the max valid value, dropped or clamped
+    pub fn rejected_count(&self) -> u64 {
+        self.rejected
+    }
+
+    /// `(time, value)` of the recent appends set a new lifetime max, oldest first,
+    /// up to the configured extreme log size
+    pub fn recent_extremes(&self) -> impl Iterator<Item = &(u32, u64)> {
+        self.extremes.iter()
+    }
+
+    /// record the value counted n times at the time into the bucket by index
+    fn record_in(&mut self, idx: usize, time: u32, value: u64, n: u32) {
+         if self.config.extreme_log_size > 0 {
+             let (min, max) = self.range_lifetime.min_max;
+             if value > max || min > max {
+                 if self.extremes.len() >= self.config.extreme_log_size {
+                     self.extremes.pop_front();
+                 }
+                 self.extremes.push_back((time, value));
+             }
+         }
+         self.range.check(value);
+         self.range_lifetime.check(value);
+         #[cfg(feature = "tdigest")]
+         if self.config.estimator == PercentileEstimator::TDigest {
+             self.buckets[idx].digest.append_n(value, n);
+         }
+         self.sketch_append(idx, value, n);
+         let band = self.range.floored(self.config.tail, self.config.min_range_width);
+         let b = &mut self.buckets[idx];
+         b.scale.get_mut(0).unwrap().append_n(value, n);
+         b.range.check(value);
+         if self.config.log_scale {
+             if b.log.is_empty() {
+                 b.log.push(Scale { sum: 0, power: 0, count: 0 });
+             }
+             b.log[0].append_n(log_fixed(value), n);
+         }
+         b.squares.append_square_n(value, n);
+         if self.totals.len() <= self.config.percentiles.len() {
+             self.totals.resize(self.config.percentiles.len() + 1, Scale { sum: 0, power: 0, count: 0 });
+         }
+         self.totals[0].append_n(value, n);
+         if self.lifetime.len() <= self.config.percentiles.len() {
+             self.lifetime.resize(self.config.percentiles.len() + 1, Scale { sum: 0, power: 0, count: 0 });
+         }
+         self.lifetime[0].append_n(value, n);
+
+         // a bucket without the percentiles is left out of them, so its scales are not padded
+         for percentile_id in (1..self.config.percentiles.len()+1).filter(|_| b.has_percentiles) {
+             if b.scale.len() <= percentile_id {
+                 b.scale.push(Scale { sum: 0, power: 0, count: 0 });
+             }
+             if self.config.log_scale && b.log.len() <= percentile_id {
+                 b.log.push(Scale { sum: 0, power: 0, count: 0 });
+             }
+
+             if band.check_in(self.config.tail, self.config.skew, self.config.percentiles[percentile_id - 1], value) {
+                 b.scale[percentile_id].append_n(value, n);
+                 if self.config.log_scale {
+                     b.log[percentile_id].append_n(log_fixed(value), n);
+                 }
+                 self.totals[percentile_id].append_n(value, n);
+                 self.lifetime[percentile_id].append_n(value, n);
+             }
+         }
+         if let Some(top_n) = self.config.top_n {
+             for _ in 0..top_n.min(n as usize) {
+                 b.push_top(value, top_n);
+             }
+         }
+    }
+
+    /// append `(time, value)` samples sorted by time, same bucket runs are recorded by a single lookup,
+    /// the samples older than the live time of the newest bucket are dropped
+    pub fn append_many_at(&mut self, samples: &[(u32, u64)]) {
+        let mut samples = samples.to_vec();
+        samples.sort_by_key(|(time, _)| *time);
+        let span = self.config.span_sec as u32;
+        let mut i = 0;
+        while i < samples.len() {
+            let (time, value) = samples[i];
+            i += 1;
+            let Some(value) = self.valid(value, 1) else {
+                continue;
+            };
+            let Some(idx) = self.slot(time) else {
+                continue;
+            };
+            self.record_in(idx, time, value, 1);
+            // the run is within the span of the bucket and before the newer bucket
+            let mut end = self.buckets[idx].time.saturating_add(span);
+            if idx > 0 && !self.config.lifetime_only {
+                end = end.min(self.buckets[idx - 1].time - 1);
+            }
+            while i < samples.len() && samples[i].0 <= end {
+                if let Some(value) = self.valid(samples[i].1, 1) {
+                    self.record_in(idx, samples[i].0, value, 1);
+                }
+                i += 1;
+            }
+            let newest = self.buckets.front().unwrap().time;
+            self.evict(newest);
+        }
+    }
+
+    /// drop buckets outlived the live time, or the tail retention if reached the top band
+    fn evict(&mut self, time: u32) {
+        let live = self.config.live_time_sec as u32;
+        if live == 0 || self.config.lifetime_only {
+            return;
+        }
+        let tail_live = live * self.config.tail_retention.unwrap_or(1) as u32;
+        let tail_edge = self.tail_edge();
+        let mut rescan = false;
+        let mut i = self.buckets.len();
+        while i > 0 && self.buckets.len() > 1 {
+            i -= 1;
+            let age = time.saturating_sub(self.buckets[i].time);
+            if age <= live {
+                break;
+            }
+            if age <= tail_live && self.buckets[i].scale[0].count > 0 && self.buckets[i].range.min_max.1 >= tail_edge {
+                continue;
+            }
+            let b = self.buckets.remove(i).unwrap();
+            rescan |= self.forget(&b);
+        }
+        if rescan {
+            // modify range after evict
+            self.range = self.buckets_range();
+        }
+    }
+
+    /// lowest value above the band of the highest percentile, max if no percentiles
+    fn tail_edge(&self) -> u64 {
+        let (min, max) = self.range.min_max;
+        match self.config.percentiles.iter().max() {
+            Some(p) if min <= max => {
+                let width = match self.config.tail {
+                    Tail::Upper => 10000f32,
+                    Tail::Both => 20000f32 / (1f32 - self.config.skew),
+                    Tail::Lower => 20000f32,
+                };
+                max - ((max - min) as f32 / width * (10000f32 - *p as f32)).round() as u64
+            }
+            _ => max,
+        }
+    }
+
+    /// `self + other` without the panic of a config mismatch
+    pub fn try_add(mut self, other: &Histogram) -> Result<Histogram, HistogramError> {
+        self.merge(other)?;
+        Ok(self)
+    }
+
+    /// merge the histograms of the same config into a copy of the first one,
+    /// buckets aligned by age as of [`Histogram::merge`] on the timeline of the first
+    pub fn merge_all(histograms: &[Histogram]) -> Result<Histogram, HistogramError> {
+        let first = histograms.first()
+            .ok_or_else(|| HistogramError::InvalidConfig("no histogram to merge".to_string()))?;
+        if histograms.iter().any(|h| h.config != first.config) {
+            return Err(HistogramError::ConfigMismatch);
+        }
+        let mut merged = first.clone();
+        for h in &histograms[1..] {
+            merged.merge(h)?;
+        }
+        Ok(merged)
+    }
+
+    /// merge other histogram of the same config,
+    /// buckets aligned by age as the timelines might have different start,
+    /// folded into the nearest bucket if the age is off the span grid
+    pub fn merge(&mut self, other: &Histogram) -> Result<(), HistogramError> {
+        if self.config != other.config {
+            return Err(HistogramError::ConfigMismatch);
+        }
+        let now = self.tick();
+        let other_now = other.now();
+        // the other lifetime covers its buckets
+        let mut lifetime = std::mem::take(&mut self.lifetime);
+        for b in other.buckets.iter().rev() {
+            let age = other_now.saturating_sub(b.time);
+            let mut b = b.clone();
+            b.time = now.saturating_sub(age);
+            self.fold_bucket_unchecked(b);
+        }
+        add_scales(&mut lifetime, &other.lifetime);
+        self.lifetime = lifetime;
+        self.range.merge(&other.range);
+        self.range_lifetime.merge(&other.range_lifetime);
+        self.rejected = self.rejected.saturating_add(other.rejected);
+        self.evict(now);
+        Ok(())
+    }
+
+    /// fold a bucket of the same config at its time of this timeline,
+    /// into the nearest bucket within the span or inserted as new one,
+    /// a bucket short of the percentile scales is left out of the percentiles
+    pub fn merge_bucket(&mut self, mut bucket: Bucket) -> Result<(), HistogramError> {
+        if bucket.scale.is_empty() || bucket.scale.len() > self.config.percentiles.len() + 1 {
+            return Err(HistogramError::ConfigMismatch);
+        }
+        bucket.check_percentiles(self.config.percentiles.len());
+        self.fold_bucket_unchecked(bucket);
+        Ok(())
+    }
+
+    /// [`Histogram::merge_bucket`] of a trusted bucket without the scales check,
+    /// the bucket range widens the ranges at once, the bucket is expected of the same config
+    pub fn fold_bucket_unchecked(&mut self, bucket: Bucket) {
+        if bucket.scale[0].count > 0 {
+            self.range.merge(&bucket.range);
+            self.range_lifetime.merge(&bucket.range);
+        }
+        self.fold_bucket(bucket.time, &bucket);
+        if bucket.has_percentiles {
+            if self.totals.len() < bucket.scale.len() {
+                self.totals.resize(bucket.scale.len(), Scale { sum: 0, power: 0, count: 0 });
+            }
+            for (t, s) in self.totals.iter_mut().zip(&bucket.scale) {
+                t.add(s);
+            }
+            add_scales(&mut self.lifetime, &bucket.scale);
+        } else {
+            // the bucket folded into excludes its own percentiles
+            self.totals = self.fold_scales();
+        }
+        let newest = self.buckets.front().unwrap().time;
+        self.evict(newest);
+        if let Some(cap) = self.ring {
+            let mut rescan = false;
+            while self.buckets.len() > cap {
+                let b = self.buckets.pop_back().unwrap();
+                rescan |= self.forget(&b);
+            }
+            if rescan {
+                self.range = self.buckets_range();
+            }
+        }
+    }
+
+    /// add bucket scales and range into the nearest bucket within the span or insert as new one,
+    /// into the single bucket if lifetime only
+    fn fold_bucket(&mut self, time: u32, bucket: &Bucket) {
+        let span = self.config.span_sec as u32;
+        if self.config.lifetime_only && !self.buckets.is_empty() {
+            self.buckets[0].fold(bucket, self.config.top_n);
+            return;
+        }
+        let nearest = self.buckets.iter().enumerate()
+            .min_by_key(|(_, b)| b.time.abs_diff(time))
+            .filter(|(_, b)| b.time.abs_diff(time) <= span)
+            .map(|(i, _)| i);
+        match nearest {
+            Some(i) => self.buckets[i].fold(bucket, self.config.top_n),
+            None => {
+                let mut b = bucket.clone();
+                b.time = time;
+                let idx = self.buckets.iter().position(|x| x.time < time).unwrap_or(self.buckets.len());
+                self.buckets.insert(idx, b);
+            }
+        }
+    }
+
+    /// split into the buckets begun before the time and the rest, of the same config and timeline,
+    /// a bucket straddling the time stays whole on the older side, the ranges are of the buckets,
+    /// the rejected count is kept by the newer
+    pub fn split_at_time(self, time_secs: u32) -> (Histogram, Histogram) {
+        let mut newer = self;
+        let idx = newer.buckets.iter().position(|b| b.time < time_secs).unwrap_or(newer.buckets.len());
+        let mut older = Histogram {
+            config: newer.config.clone(),
+            start: newer.start,
+            epoch: newer.epoch,
+            buckets: newer.buckets.split_off(idx),
+            range: Default::default(),
+            range_lifetime: Default::default(),
+            totals: vec![],
+            rejected: 0,
+            lifetime: vec![],
+            ring: newer.ring,
+            extremes: VecDeque::new(),
+            clock: newer.clock,
+        };
+        for h in [&mut older, &mut newer] {
+            h.range = h.buckets_range();
+            h.range_lifetime = h.range.clone();
+            h.totals = h.fold_scales();
+        }
+        (older, newer)
+    }
+
+    /// copy with adjacent buckets merged into `target_buckets` evenly sized groups,
+    /// a merged bucket starts at the oldest time of the group and spans wider than configured
+    pub fn downsample(&self, target_buckets: usize) -> Histogram {
+        let mut h = Histogram {
+            config: self.config.clone(),
+            start: self.start,
+            epoch: self.epoch,
+            buckets: VecDeque::with_capacity(target_buckets),
+            range: self.range.clone(),
+            range_lifetime: self.range_lifetime.clone(),
+            totals: self.totals.clone(),
+            rejected: self.rejected,
+            lifetime: self.lifetime.clone(),
+            ring: self.ring,
+            extremes: self.extremes.clone(),
+            clock: self.clock,
+        };
+        let len = self.buckets.len();
+        if target_buckets == 0 || len <= target_buckets {
+            h.buckets = self.buckets.clone();
+            return h;
+        }
+        for g in 0..target_buckets {
+            let group = self.buckets.range(g * len / target_buckets..(g + 1) * len / target_buckets);
+            let mut merged: Option<Bucket> = None;
+            for b in group.rev() {
+                match merged.as_mut() {
+                    Some(m) => m.fold(b, self.config.top_n),
+                    None => merged = Some(b.clone()),
+                }
+            }
+            h.buckets.extend(merged);
+        }
+        h
+    }
+
+    /// `(time, mean)` of adjacent buckets merged into `points` evenly sized groups for a chart, oldest first,
+    /// a point is at the oldest time of its group, a point per bucket if fewer buckets
+    pub fn timeseries(&self, points: usize) -> Vec<(u32, u64)> {
+        let len = self.buckets.len();
+        let points = points.min(len);
+        (0..points).rev()
+            .map(|g| {
+                let mut s = Scale { sum: 0, power: 0, count: 0 };
+                let mut time = 0;
+                for b in self.buckets.range(g * len / points..(g + 1) * len / points) {
+                    s.add(&b.scale[0]);
+                    time = b.time;
+                }
+                (time, s.avg_rounded(self.config.rounding))
+            })
+            .collect()
+    }
+
+    /// copy with all values multiplied by the factor, i.e. 0.001 to convert micros to millis,
+    /// sums, ranges, top values and the relative error bins are rounded to nearest and saturated, counts are kept
+    pub fn scaled(&self, factor: f64) -> Histogram {
+        let mut h = self.clone();
+        for b in h.buckets.iter_mut() {
+            for s in b.scale.iter_mut() {
+                *s = s.scaled(factor);
+            }
+            b.range = b.range.scaled(factor);
+            if factor > 0f64 {
+                for l in b.log.iter_mut() {
+                    let shift = l.count as f64 * factor.ln() * LOG_SCALE;
+                    l.set_total((l.total() as f64 + shift).max(0f64).round() as u128);
+                }
+            }
+            b.squares.set_total((b.squares.total() as f64 * factor * factor).round() as u128);
+            for v in b.top.iter_mut() {
+                *v = scale_value(*v, factor);
+            }
+            if let Some(error) = self.relative_error() {
+                b.sketch = b.sketch.scaled(factor, error);
+            }
+            #[cfg(feature = "tdigest")]
+            {
+                b.digest = b.digest.scaled(factor);
+            }
+        }
+        h.range = h.range.scaled(factor);
+        h.range_lifetime = h.range_lifetime.scaled(factor);
+        for s in h.lifetime.iter_mut() {
+            *s = s.scaled(factor);
+        }
+        h.totals = h.fold_scales();
+        h
+    }
+
+    /// drop buckets with less than `min_count` samples except the newest one still filling up,
+    /// so the `sample_count` drops as well
+    pub fn prune_sparse(&mut self, min_count: u64) {
+        let len = self.buckets.len();
+        let mut idx = 0;
+        self.buckets.retain(|b| {
+            idx += 1;
+            idx == 1 || b.scale[0].count >= min_count
+        });
+        if self.buckets.len() < len {
+            self.range = self.buckets_range();
+            self.totals = self.fold_scales();
+        }
+    }
+
+    /// drop the oldest buckets to keep at most `max_buckets`, regardless of the live time
+    pub fn trim(&mut self, max_buckets: usize) {
+        if self.buckets.len() > max_buckets {
+            self.buckets.truncate(max_buckets);
+            self.range = self.buckets_range();
+            self.totals = self.fold_scales();
+        }
+    }
+
+    /// lookup range of retained buckets
+    fn buckets_range(&self) -> Range {
+        let mut r = Range::default();
+        for x in &self.buckets {
+            r.merge(&x.range);
+        }
+        r
+    }
+
+    /// rebuild the range and the lifetime range of the retained buckets,
+    /// i.e. after a manual change of the buckets, the lifetime range drops the evicted extremes
+    pub fn recompute_range(&mut self) {
+        self.range = self.buckets_range();
+        self.range_lifetime = self.range.clone();
+    }
+
+    /// reset lifetime range and percentile scales to the retained buckets, i.e. after deploy
+    pub fn reset_lifetime(&mut self) {
+        self.range_lifetime = self.buckets_range();
+        self.lifetime = self.fold_scales();
+    }
+
+    pub fn median(&self) -> u64 {
+        let min = self.range.min_max.0;
+        min + (self.range.min_max.1 - min) / 2
+    }
+
+    /// largest retained sample, none if empty
+    pub fn max(&self) -> Option<u64> {
+        if self.sample_count() == 0 {
+            None
+        } else {
+            Some(self.range.min_max.1)
+        }
+    }
+
+    pub fn median_lt(&self) -> u64 {
+        let min = self.range_lifetime.min_max.0;
+        min + (self.range_lifetime.min_max.1 - min) / 2
+    }
+
+    pub fn min_lt(&self) -> u64 {
+        self.range_lifetime.min_max.0
+    }
+
+    pub fn max_lt(&self) -> u64 {
+        self.range_lifetime.min_max.1
+    }
+
+    /// arithmetic mean of the retained samples, same as [`Histogram::mean`] but 0 if empty,
+    /// unlike [`Histogram::median`] which is the middle of the range
+    pub fn average(&self) -> u64 {
+        self.mean().unwrap_or(0)
+    }
+
+    /// count weighted arithmetic mean of all retained samples, overflow safe
+    pub fn mean(&self) -> Option<u64> {
+        let s = self.scale_by_id(0);
+        if s.count == 0 || self.warming_up() {
+            None
+        } else {
+            Some(s.avg_rounded(self.config.rounding))
+        }
+    }
+
+    /// within the warmup period, the stats are not reported
+    #[inline]
+    pub fn warming_up(&self) -> bool {
+        self.elapsed() < Duration::from_secs(self.config.warmup_sec as u64)
+    }
+
+    /// all retained samples folded into one scale
+    pub fn total_scale(&self) -> Scale {
+        self.scale_by_id(0)
+    }
+
+    /// [`Histogram::mean`] as the duration in the configured time unit
+    pub fn mean_duration(&self) -> Option<Duration> {
+        self.mean().map(|v| self.config.time_unit.duration(v))
+    }
+
+    /// mean of the fully elapsed buckets, without the front bucket still filling within the span
+    pub fn mean_complete(&self) -> Option<u64> {
+        let now = self.now();
+        let skip = self.buckets.front()
+            .is_some_and(|b| now.saturating_sub(b.time) <= self.config.span_sec as u32) as usize;
+        let mut s = Scale { sum: 0, power: 0, count: 0 };
+        for b in self.buckets.iter().skip(skip) {
+            s.add(&b.scale[0]);
+        }
+        if s.count == 0 || self.warming_up() {
+            None
+        } else {
+            Some(s.avg_rounded(self.config.rounding))
+        }
+    }
+
+    /// percentile value by the configured estimator, the average of the samples within the band by default
+    pub fn average_p(&self, percentile: u8) -> Result<u64, HistogramError> {
+        let pid = self.config.find(percentile)?;
+        self.checked_estimate(pid, &self.scale_by_id(pid))
+    }
+
+    /// [`Histogram::average_p`] rounded to nearest of the significant figures for display,
+    /// i.e. 48123 of 2 figures is 48000, 0 figures keep the value
+    pub fn average_p_sig(&self, percentile: u8, sig_figs: u8) -> Result<u64, HistogramError> {
+        Ok(round_sig(self.average_p(percentile)?, sig_figs))
+    }
+
+    /// [`Histogram::average_p`] as the duration in the configured time unit
+    pub fn average_p_duration(&self, percentile: u8) -> Result<Duration, HistogramError> {
+        self.average_p(percentile).map(|v| self.config.time_unit.duration(v))
+    }
+
+    /// average if enough samples for a percentile, 0 of an empty band if no more than 1 is required
+    #[inline]
+    fn checked_avg(&self, s: &Scale) -> Result<u64, HistogramError> {
+        let min = self.config.min_samples_for_percentile as u64;
+        if min > 1 && s.count < min {
+            return Err(HistogramError::InsufficientSamples);
+        }
+        Ok(s.avg_rounded(self.config.rounding))
+    }
+
+    /// percentile value by scale index of the aggregated band scale if enough samples
+    fn checked_estimate(&self, pid: usize, s: &Scale) -> Result<u64, HistogramError> {
+        if self.warming_up() {
+            return Err(HistogramError::Warmup);
+        }
+        match self.config.estimator {
+            PercentileEstimator::RangeBand => self.checked_avg(s),
+            PercentileEstimator::WeightedBuckets => self.quantile(self.config.percentiles[pid - 1] as f64 / 10000f64),
+            #[cfg(feature = "tdigest")]
+            PercentileEstimator::TDigest => self.quantile(self.config.percentiles[pid - 1] as f64 / 10000f64),
+        }
+    }
+
+    /// percentile value by scale index, 0 if empty or warming up
+    fn estimate(&self, pid: usize, s: &Scale) -> u64 {
+        match self.config.estimator {
+            PercentileEstimator::RangeBand if self.warming_up() => 0,
+            PercentileEstimator::RangeBand => s.avg_rounded(self.config.rounding),
+            PercentileEstimator::WeightedBuckets => self.checked_estimate(pid, s).unwrap_or(0),
+            #[cfg(feature = "tdigest")]
+            PercentileEstimator::TDigest => self.checked_estimate(pid, s).unwrap_or(0),
+        }
+    }
+
+    /// percentile values at once
+    pub fn averages_p(&self, percentiles: &[u8]) -> Result<Vec<(u8, u64)>, HistogramError> {
+        let scales = self.scales();
+        percentiles.iter()
+            .map(|p| {
+                let pid = self.config.find(*p)?;
+                Ok((*p, self.checked_estimate(pid, &scales[pid])?))
+            })
+            .collect()
+    }
+
+    /// whether the percentile value is above the threshold, i.e. to alert of p99 over 500
+    pub fn exceeds(&self, percentile: u8, threshold: u64) -> Result<bool, HistogramError> {
+        Ok(self.average_p(percentile)? > threshold)
+    }
+
+    /// whether any of the `(percentile, threshold)` checks exceeds, the values of [`Histogram::averages_p`] at once
+    pub fn exceeds_any(&self, checks: &[(u8, u64)]) -> Result<bool, HistogramError> {
+        let percentiles: Vec<u8> = checks.iter().map(|c| c.0).collect();
+        let values = self.averages_p(&percentiles)?;
+        Ok(values.iter().zip(checks).any(|((_, v), (_, threshold))| v > threshold))
+    }
+
+    /// aggregated scales of all samples and by every configured percentile, in a single pass
+    fn fold_scales(&self) -> Vec<Scale> {
+        let mut r = vec![Scale { sum: 0, power: 0, count: 0 }; self.config.percentiles.len() + 1];
+        for b in &self.buckets {
+            let scales = if b.has_percentiles { b.scale.len() } else { 1 };
+            for (s, x) in r.iter_mut().zip(&b.scale[..scales]) {
+                s.add(x);
+            }
+        }
+        r
+    }
+
+    /// aggregated scale of the percentile band over the retained buckets
+    pub fn scale_p(&self, percentile: u8) -> Result<Scale, HistogramError> {
+        Ok(self.scale_by_id(self.config.find(percentile)?))
+    }
+
+    /// aggregated scales of all samples and by every configured percentile
+    fn scales(&self) -> Vec<Scale> {
+        (0..self.config.percentiles.len() + 1).map(|i| self.scale_by_id(i)).collect()
+    }
+
+    /// aggregated scale by index, 0 is all samples
+    fn scale_by_id(&self, pid: usize) -> Scale {
+        if pid > 0 && self.config.window == PercentileWindow::Lifetime {
+            return self.lifetime.get(pid).cloned().unwrap_or(Scale { sum: 0, power: 0, count: 0 });
+        }
+        match self.totals.get(pid) {
+            Some(s) => s.clone(),
+            None => self.fold_scale(pid),
+        }
+    }
+
+    /// scale by index folded over the buckets
+    fn fold_scale(&self, pid: usize) -> Scale {
+        let mut r = Scale { sum: 0, power: 0, count: 0 };
+        // buckets recorded before the percentile was configured have no scale for it
+        for b in self.buckets.iter().filter(|b| b.scale.len() > pid && (pid == 0 || b.has_percentiles)) {
+            r.add(&b.scale[pid])
+        }
+        r
+    }
+
+    /// summary of the retained samples, all zeros if empty, the mean, median and percentiles are 0 while warming up
+    pub fn snapshot(&self) -> Snapshot {
+        let count = self.sample_count();
+        let (min, max, median) = if count == 0 { (0, 0, 0) } else { (self.range.min_max.0, self.range.min_max.1, self.median()) };
+        let scales = self.scales();
+        let percentiles = self.config.percentiles.iter().zip(&scales[1..]).enumerate()
+            .map(|(i, (p, s))| (*p, self.estimate(i + 1, s)))
+            .collect();
+        let (mean, median) = if self.warming_up() { (0, 0) } else { (scales[0].avg_rounded(self.config.rounding), median) };
+        Snapshot { count, mean, min, max, median, percentiles }
+    }
+
+    /// time of the bucket the percentile average shifts from, splitting the buckets chronologically
+    /// at the largest relative shift of the band averages before and after,
+    /// none if stable within the `sensitivity` share of the larger average, or an unknown percentile
+    pub fn percentile_changepoint(&self, percentile: u8, sensitivity: f64) -> Option<u32> {
+        let pid = self.config.find(percentile).ok()?;
+        let buckets: Vec<&Bucket> = self.buckets.iter().rev()
+            .filter(|b| b.has_percentiles && b.scale.get(pid).is_some_and(|s| s.count > 0))
+            .collect();
+        let mut after = Scale { sum: 0, power: 0, count: 0 };
+        for b in &buckets {
+            after.add(&b.scale[pid]);
+        }
+        let mut before = Scale { sum: 0, power: 0, count: 0 };
+        let mut found = None;
+        let mut shift = sensitivity;
+        for pair in buckets.windows(2) {
+            before.add(&pair[0].scale[pid]);
+            after.sub(&pair[0].scale[pid]);
+            let (a, b) = (before.avg() as f64, after.avg() as f64);
+            let s = (b - a).abs() / a.max(b).max(1f64);
+            if s > shift {
+                shift = s;
+                found = Some(pair[1].time);
+            }
+        }
+        found
+    }
+
+    /// `(time, count)` of the bucket with the most samples, the most recent of equal ones, none if empty
+    pub fn peak_bucket(&self) -> Option<(u32, u64)> {
+        self.buckets.iter().rev()
+            .filter(|b| b.scale[0].count > 0)
+            .max_by_key(|b| b.scale[0].count)
+            .map(|b| (b.time, b.scale[0].count))
+    }
+
+    /// standard deviation of the per bucket averages of the percentile, high if the tail is unstable,
+    /// none if fewer than two buckets of the percentile samples
+    pub fn percentile_stddev(&self, percentile: u8) -> Option<f64> {
+        let pid = self.config.find(percentile).ok()?;
+        let avgs: Vec<f64> = self.buckets.iter()
+            .filter(|b| b.has_percentiles && b.scale.get(pid).is_some_and(|s| s.count > 0))
+            .map(|b| b.scale[pid].avg() as f64)
+            .collect();
+        if avgs.len() < 2 {
+            return None;
+        }
+        let mean = avgs.iter().sum::<f64>() / avgs.len() as f64;
+        let var = avgs.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / avgs.len() as f64;
+        Some(var.sqrt())
+    }
+
+    /// `(time, stddev)` of the buckets of two samples at least, newest first, i.e. for the error bars of the means
+    pub fn bucket_stddevs(&self) -> Vec<(u32, f64)> {
+        self.buckets.iter().filter_map(|b| b.stddev().map(|s| (b.time, s))).collect()
+    }
+
+    /// geometric mean of the samples of the log scales if configured, or derived of the bucket averages
+    /// so never below the exact one, a zero sample is counted as one, none if empty or warming up
+    pub fn geometric_mean(&self) -> Option<f64> {
+        if self.warming_up() {
+            return None;
+        }
+        let (mut count, mut logs) = (0u64, 0f64);
+        for b in self.buckets.iter().filter(|b| b.scale[0].count > 0) {
+            count += b.scale[0].count;
+            logs += if self.config.log_scale {
+                b.log.first().map_or(0f64, |l| l.total() as f64 / LOG_SCALE)
+            } else {
+                b.scale[0].count as f64 * (b.scale[0].avg().max(1) as f64).ln()
+            };
+        }
+        if count == 0 {
+            return None;
+        }
+        Some((logs / count as f64).exp())
+    }
+
+    /// geometric mean of the samples within the percentile band, exact of the log scales of the bands
+    /// if configured, a zero sample is counted as one, of the retained buckets with the percentiles
+    pub fn geometric_average_p(&self, percentile: u8) -> Result<f64, HistogramError> {
+        let pid = self.config.find(percentile)?;
+        if !self.config.log_scale {
+            return Err(HistogramError::InvalidConfig("'log_scale' is not set".to_string()));
+        }
+        if self.warming_up() {
+            return Err(HistogramError::Warmup);
+        }
+        let (mut count, mut logs) = (0u64, 0f64);
+        for l in self.buckets.iter().filter(|b| b.has_percentiles).filter_map(|b| b.log.get(pid)) {
+            count += l.count;
+            logs += l.total() as f64 / LOG_SCALE;
+        }
+        if count == 0 || count < self.config.min_samples_for_percentile as u64 {
+            return Err(HistogramError::InsufficientSamples);
+        }
+        Ok((logs / count as f64).exp())
+    }
+
+    /// summary of every bucket at the wall-clock time of its begin as `epoch` plus the offset,
+    /// newest first, the `epoch` of this timeline is [`Histogram::epoch`]
+    pub fn buckets_with_systemtime(&self, epoch: SystemTime) -> Vec<(SystemTime, Snapshot)> {
+        self.buckets.iter()
+            .map(|b| (epoch + Duration::from_secs(b.time as u64), self.bucket_summary(b)))
+            .collect()
+    }
+
+    /// summary of the buckets grouped into the slots of the interval from the `epoch`, newest first,
+    /// i.e. of a minute from the `UNIX_EPOCH` for the per minute rows, a bucket is of the slot
+    /// its begin falls in, at [`Histogram::epoch`] plus the bucket time, an interval is a second at least
+    pub fn aligned_buckets(&self, epoch: SystemTime, interval: Duration) -> Vec<(SystemTime, Snapshot)> {
+        let interval = interval.max(Duration::from_secs(1));
+        let slot = |b: &Bucket| {
+            let begin = self.epoch + Duration::from_secs(b.time as u64);
+            let step = interval.as_nanos();
+            match begin.duration_since(epoch) {
+                Ok(d) => epoch + Duration::from_nanos((d.as_nanos() / step * step) as u64),
+                Err(e) => epoch - Duration::from_nanos((e.duration().as_nanos().div_ceil(step) * step) as u64),
+            }
+        };
+        let mut slots: Vec<(SystemTime, Bucket)> = vec![];
+        for b in &self.buckets {
+            let time = slot(b);
+            match slots.last_mut() {
+                Some((t, merged)) if *t == time => merged.fold(b, self.config.top_n),
+                _ => slots.push((time, b.clone())),
+            }
+        }
+        slots.iter().map(|(t, b)| (*t, self.bucket_summary(b))).collect()
+    }
+
+    /// summary of the bucket, min and max are 0 if empty
+    fn bucket_summary(&self, b: &Bucket) -> Snapshot {
+        let count = b.scale[0].count;
+        let (min, max) = if count == 0 { (0, 0) } else { b.range.min_max };
+        let percentiles = self.config.percentiles.iter().enumerate()
+            .map(|(i, p)| (*p, b.scale.get(i + 1).map_or(0, |s| s.avg_rounded(self.config.rounding))))
+            .collect();
+        Snapshot {
+            count,
+            mean: b.scale[0].avg_rounded(self.config.rounding),
+            min,
+            max,
+            median: min + (max - min) / 2,
+            percentiles,
+        }
+    }
+
+    /// lazy `(time, summary)` of every bucket, newest first
+    pub fn bucket_snapshots(&self) -> impl Iterator<Item = (u32, BucketSnapshot)> + '_ {
+        self.buckets.iter().map(|b| {
+            let count = b.scale[0].count;
+            let (min, max) = if count == 0 { (0, 0) } else { b.range.min_max };
+            (b.time, BucketSnapshot { count, min, max, mean: b.scale[0].avg_rounded(self.config.rounding) })
+        })
+    }
+
+    /// `key=value` line of the configured percentile averages and the count, i.e. `p90=48 p99.9=97 n=101`,
+    /// a percentile with less samples than required is left out
+    pub fn log_line(&self) -> String {
+        let scales = self.scales();
+        let mut line = String::new();
+        if scales[0].count > 0 {
+            for (i, (p, s)) in self.config.percentiles.iter().zip(&scales[1..]).enumerate() {
+                if let Ok(avg) = self.checked_estimate(i + 1, s) {
+                    line.push_str(&format!("{}={} ", percentile_label(*p), avg));
+                }
+            }
+        }
+        line.push_str(&format!("n={}", scales[0].count));
+        line
+    }
+
+    /// signed drift of the configured percentile averages from the baseline,
+    /// by percentiles in hundredths present on both sides, none while warming up
+    pub fn compare_percentiles(&self, baseline: &Snapshot) -> Vec<(u16, i64)> {
+        if self.warming_up() {
+            return vec![];
+        }
+        self.config.percentiles.iter().enumerate()
+            .filter_map(|(i, p)| {
+                let (_, base) = baseline.percentiles.iter().find(|(bp, _)| bp == p)?;
+                let delta = self.estimate(i + 1, &self.scale_by_id(i + 1)) as i128 - *base as i128;
+                Some((*p, delta.clamp(i64::MIN as i128, i64::MAX as i128) as i64))
+            })
+            .collect()
+    }
+
+    pub fn buckets(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn sample_count(&self) -> u64 {
+        let mut s = 0u64;
+        for b in &self.buckets {
+            s += b.scale[0].count;
+        }
+        s
+    }
+
+    pub fn sample_count_p(&self, percentile: u8) -> Result<u64, HistogramError> {
+        let pid = self.config.find(percentile)?;
+        let mut s = 0u64;
+        for b in self.buckets.iter().filter(|b| b.scale.len() > pid && b.has_percentiles) {
+            s += b.scale[pid].count;
+        }
+        Ok(s)
+    }
+
+    /// in band samples of the percentile ever appended or merged, never evicted,
+    /// i.e. requests ever exceeded the SLO, since start or [`Histogram::reset_lifetime`]
+    pub fn lifetime_count_p(&self, percentile: u8) -> Result<u64, HistogramError> {
+        let pid = self.config.find(percentile)?;
+        Ok(self.lifetime.get(pid).map_or(0, |s| s.count))
+    }
+
+    /// `(time, in band count)` of every bucket for the percentile, newest first,
+    /// to tell a tail of a spike from a spread one, 0 of a bucket without the percentiles
+    pub fn percentile_contributions(&self, percentile: u8) -> Result<Vec<(u32, u64)>, HistogramError> {
+        let pid = self.config.find(percentile)?;
+        Ok(self.buckets.iter()
+            .map(|b| (b.time, if b.has_percentiles { b.scale.get(pid).map_or(0, |s| s.count) } else { 0 }))
+            .collect())
+    }
+
+    /// 0..1 trust score of the percentile estimate:
+    /// share of the expected in-band samples actually in band, damped by `1/sqrt(n)` for small samples
+    pub fn percentile_confidence(&self, percentile: u8) -> Option<f64> {
+        let pid = self.config.find(percentile).ok()?;
+        let total = self.sample_count();
+        if total == 0 {
+            return None;
+        }
+        let n = self.sample_count_p(percentile).ok()?;
+        Some(confidence(total, n, self.config.percentiles[pid - 1], self.config.tail))
+    }
+
+    /// all configured percentiles with the counts and confidences at once
+    pub fn percentile_report(&self) -> Vec<PercentileResult> {
+        let total = self.sample_count();
+        let scales = self.scales();
+        self.config.percentiles.iter().zip(&scales[1..]).enumerate()
+            .map(|(i, (p, s))| PercentileResult {
+                percentile: *p,
+                average: self.estimate(i + 1, s),
+                count: s.count,
+                confidence: if total == 0 { 0f64 } else { confidence(total, s.count, *p, self.config.tail) },
+                excluded: self.buckets.iter()
+                    .filter(|b| !b.has_percentiles || b.scale.len() <= i + 1)
+                    .map(|b| b.time)
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// the estimates of the configured percentiles do not decrease by the percentile,
+    /// a percentile of not enough samples is skipped, a sanity check of the estimator
+    pub fn percentiles_monotonic(&self) -> bool {
+        let scales = self.scales();
+        let mut values: Vec<(u16, u64)> = self.config.percentiles.iter().enumerate()
+            .filter_map(|(i, p)| self.checked_estimate(i + 1, &scales[i + 1]).ok().map(|v| (*p, v)))
+            .collect();
+        values.sort_unstable();
+        values.windows(2).all(|w| w[0].1 <= w[1].1)
+    }
+
+    /// time of the oldest retained bucket
+    pub fn oldest_time(&self) -> Option<u32> {
+        self.buckets.back().map(|b| b.time)
+    }
+
+    /// time of the newest retained bucket
+    pub fn newest_time(&self) -> Option<u32> {
+        self.buckets.front().map(|b| b.time)
+    }
+
+    /// average of the configured N largest retained samples
+    pub fn top_n_average(&self) -> Option<u64> {
+        let n = self.config.top_n?;
+        let mut top: Vec<u64> = self.buckets.iter().flat_map(|b| b.top.iter().copied()).collect();
+        if top.is_empty() || n == 0 {
+            return None;
+        }
+        top.sort_unstable_by(|a, b| b.cmp(a));
+        top.truncate(n);
+        let sum: u128 = top.iter().map(|v| *v as u128).sum();
+        Some((sum / top.len() as u128) as u64)
+    }
+
+    /// times of retained buckets without any samples
+    pub fn empty_buckets(&self) -> Vec<u32> {
+        self.buckets.iter()
+            .filter(|b| b.scale[0].count == 0)
+            .map(|b| b.time)
+            .collect()
+    }
+
+    /// estimated count of samples great than the threshold
+    pub fn count_above(&self, threshold: u64) -> u64 {
+        self.count_above_f(threshold).round() as u64
+    }
+
+    #[inline]
+    fn count_above_f(&self, threshold: u64) -> f64 {
+        let mut s = 0f64;
+        for b in &self.buckets {
+            s += b.count_above(threshold);
+        }
+        s
+    }
+
+    /// estimated count of samples within `low..=high`, 0 if `low > high`
+    pub fn count_between(&self, low: u64, high: u64) -> u64 {
+        if low > high {
+            return 0;
+        }
+        let s = self.sample_count() as f64 - self.count_below_f(low) - self.count_above_f(high);
+        s.max(0f64).round() as u64
+    }
+
+    /// estimated count of samples less than the threshold
+    pub fn count_below(&self, threshold: u64) -> u64 {
+        self.count_below_f(threshold).round() as u64
+    }
+
+    #[inline]
+    fn count_below_f(&self, threshold: u64) -> f64 {
+        let mut s = 0f64;
+        for b in &self.buckets {
+            s += b.count_below(threshold);
+        }
+        s
+    }
+
+    /// estimated whole percent of samples less than the value, none if empty
+    pub fn percentile_rank(&self, value: u64) -> Option<u8> {
+        let total = self.sample_count();
+        if total == 0 {
+            return None;
+        }
+        Some((self.count_below_f(value) * 100f64 / total as f64).clamp(0f64, 100f64) as u8)
+    }
+
+    /// percentile rank of the max, high if the max is a lone outlier, low if representative
+    pub fn percentile_of_max(&self) -> Option<u8> {
+        self.percentile_rank(self.max()?)
+    }
+
+    /// two peaks split by a valley deeper than the `threshold` share of the highest peak,
+    /// over the counts of the range split in bins, uniform within each bucket range,
+    /// so the modes mixed in the same buckets are not told apart, none if empty
+    pub fn is_bimodal(&self, threshold: f64) -> Option<bool> {
+        const BINS: u64 = 20;
+        if self.sample_count() == 0 {
+            return None;
+        }
+        let (min, max) = self.range.min_max;
+        if max - min < BINS {
+            return Some(false);
+        }
+        let edge = |i: u64| min + ((max - min) as u128 * i as u128 / BINS as u128) as u64;
+        let bins: Vec<f64> = (0..BINS)
+            .map(|i| {
+                let high = if i + 1 == BINS { max.saturating_add(1) } else { edge(i + 1) };
+                self.count_below_f(high) - self.count_below_f(edge(i))
+            })
+            .collect();
+        let (top, peak) = bins.iter().enumerate()
+            .fold((0, 0f64), |r, (i, c)| if *c > r.1 { (i, *c) } else { r });
+        let dip = (0..bins.len())
+            .map(|j| {
+                let valley = bins[top.min(j)..=top.max(j)].iter().cloned().fold(f64::MAX, f64::min);
+                (bins[j].min(peak) - valley) / peak
+            })
+            .fold(0f64, f64::max);
+        Some(dip > threshold)
+    }
+
+    /// estimated value below which the `p` (0..=1) share of samples falls, uniform within each bucket,
+    /// or of the relative error bins or the t-digests if configured and covering every sample, otherwise of the buckets,
+    /// [`HistogramError::InsufficientSamples`] if empty or less samples than required for a percentile
+    pub fn quantile(&self, p: f64) -> Result<u64, HistogramError> {
+        if !(0f64..=1f64).contains(&p) {
+            return Err(HistogramError::UnknownPercentile(format!("{} is out of 0..=1", p)));
+        }
+        if self.warming_up() {
+            return Err(HistogramError::Warmup);
+        }
+        let total = self.sample_count();
+        if total == 0 || total < self.config.min_samples_for_percentile as u64 {
+            return Err(HistogramError::InsufficientSamples);
+        }
+        if let Some(v) = self.sketch_quantile(p) {
+            return Ok(v);
+        }
+        #[cfg(feature = "tdigest")]
+        if let Some(v) = self.digest_quantile(p) {
+            return Ok(v);
+        }
+        let rank = p * total as f64;
+        let (mut lo, mut hi) = self.range.min_max;
+        // smallest value with the rank of samples below
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.count_below_f(mid) >= rank {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        if self.config.rounding == Rounding::RoundNearest && lo > self.range.min_max.0 {
+            // the rank is crossed between lo - 1 and lo, take the closer one
+            let (a, b) = (self.count_below_f(lo - 1), self.count_below_f(lo));
+            if b > a && (rank - a) / (b - a) < 0.5 {
+                lo -= 1;
+            }
+        }
+        Ok(lo)
+    }
+
+    /// [`Histogram::quantile`] as the duration in the configured time unit
+    pub fn quantile_duration(&self, p: f64) -> Result<Duration, HistogramError> {
+        self.quantile(p).map(|v| self.config.time_unit.duration(v))
+    }
+
+    /// quantile with the `(low, point, high)` bounds, the bounds are the quantiles
+    /// as if all samples of each bucket were at the min or at the max of the bucket range
+    pub fn quantile_with_bounds(&self, p: f64) -> Option<(u64, u64, u64)> {
+        let point = self.quantile(p).ok()?;
+        let rank = ((p * self.sample_count() as f64).ceil() as u64).max(1);
+        let bound = |edge: fn(&Range) -> u64| {
+            let mut values: Vec<(u64, u64)> = self.buckets.iter()
+                .filter(|b| b.scale[0].count > 0)
+                .map(|b| (edge(&b.range), b.scale[0].count))
+                .collect();
+            values.sort_unstable();
+            let mut n = 0;
+            for (v, c) in &values {
+                n += c;
+                if n >= rank {
+                    return *v;
+                }
+            }
+            values.last().map_or(point, |v| v.0)
+        };
+        let low = bound(Range::min);
+        let high = bound(Range::max);
+        Some((low, point.clamp(low, high), high))
+    }
+
+    /// bound of the relative error of [`Histogram::quantile`], the configured one of the relative error bins,
+    /// otherwise the farther of the [`Histogram::quantile_with_bounds`] to the value, none if no quantile
+    pub fn quantile_error(&self, p: f64) -> Option<f64> {
+        if let Some(error) = self.relative_error() {
+            if self.quantile(p).is_ok() && self.sketch_quantile(p).is_some() {
+                return Some(error);
+            }
+        }
+        let (low, point, high) = self.quantile_with_bounds(p)?;
+        Some((point - low).max(high - point) as f64 / point.max(1) as f64)
+    }
+
+}
+
+/// copy of the histogram internals
+#[cfg(feature = "testing")]
+#[derive(Clone, Debug)]
+pub struct DebugView {
+    /// newest first
+    pub buckets: Vec<Bucket>,
+    pub range: Range,
+    pub range_lifetime: Range,
+    pub config: Config,
+}
+
+#[cfg(feature = "testing")]
+impl Histogram {
+    /// cloned internals to assert in tests
+    pub fn debug_dump(&self) -> DebugView {
+        DebugView {
+            buckets: self.buckets.iter().cloned().collect(),
+            range: self.range.clone(),
+            range_lifetime: self.range_lifetime.clone(),
+            config: (*self.config).clone(),
+        }
+    }
+}
+
+impl Extend<u64> for Histogram {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        self.extend_from(iter)
+    }
+}
+
+/// [`Histogram::merge`], panics if configured differently, see [`Histogram::try_add`]
+impl Add for Histogram {
+    type Output = Histogram;
+
+    fn add(mut self, other: Histogram) -> Histogram {
+        self += other;
+        self
+    }
+}
+
+/// [`Histogram::merge`], panics if configured differently
+impl AddAssign for Histogram {
+    fn add_assign(&mut self, other: Histogram) {
+        if let Err(e) = self.merge(&other) {
+            panic!("histogram add: {}", e);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// empty histogram of the range as if already seen, so the bands are of the whole range from the first sample
+    fn seeded(config: Config, min_max: (u64, u64)) -> Histogram {
+        let mut h = Histogram::new(config);
+        h.range = Range { min_max };
+        h
+    }
+
+    /// 1000 values of 10 seconds appended in halves on the same timeline and merged, then through
+    /// the encodings, `merge_bucket`, `split_at_time` and `scaled`, each checked of the values it holds,
+    /// the merged histogram for the checks of the fallback
+    pub(crate) fn assert_kept(config: Config, values: &[u64], within: impl Fn(&Histogram, &[u64])) -> Histogram {
+        assert_eq!(values.len(), 1000);
+        // of the same timeline so the merged buckets keep their times
+        let start = Instant::now() - Duration::from_secs(20);
+        let mut a = Histogram::with_start(config.clone(), start);
+        let mut b = Histogram::with_start(config.clone(), start);
+        for (i, v) in values.iter().enumerate() {
+            if i % 2 == 0 { a.append_at(i as u32 / 100, *v) } else { b.append_at(i as u32 / 100, *v) }
+        }
+        a.merge(&b).unwrap();
+        within(&a, values);
+
+        let mut out = Vec::new();
+        a.encode(&mut out);
+        assert_eq!(Histogram::decode(&out).unwrap().buckets, a.buckets);
+        assert_eq!(a.serialized_size_hint(), out.len());
+        assert_eq!(Histogram::from_json(&a.to_json_v1()).unwrap().buckets, a.buckets);
+
+        let mut m = Histogram::with_start(config, start);
+        for bucket in a.buckets.iter().rev() {
+            m.merge_bucket(bucket.clone()).unwrap();
+        }
+        within(&m, values);
+
+        // of the inclusive bucket spans the 6th second is of the older
+        let (older, newer) = a.clone().split_at_time(6);
+        within(&older, &values[..600]);
+        within(&newer, &values[600..]);
+
+        let scaled: Vec<u64> = values.iter().map(|v| v * 3).collect();
+        within(&a.scaled(3f64), &scaled);
+        a
+    }
+
+    #[test]
+    fn test() {
+        // let power = u64::MAX as u128 * u64::MAX as u128;
+        // assert!(power > u64::MAX as u128);
+        let mut h = Histogram::new(Config::default());
+        h.append(0);
+        assert_eq!(h.median(), 0);
+        h.append(2);
+        assert_eq!(h.median(), 1);
+    }
+
+    #[test]
+    fn test_p() {
+        let mut h = Histogram::new(Config {
+            percentiles: vec![9500],
+            span_sec: 1,
+            live_time_sec: 100,
+            ..Default::default()
+        });
+        h.append(0);
+        h.append(100);
+        assert_eq!(h.median(), 50);
+        for x in 1..101 {
+            h.append(x);
+        }
+        assert_eq!(h.median(), 50);
+        assert_eq!(h.average(), 50);
+        assert_eq!(h.average_p(95).unwrap(), 49);
+        assert_eq!(h.average_p(0 /* by index */).unwrap(), 49);
+        assert_eq!(h.sample_count(), 102);
+        assert_eq!(h.sample_count_p(95).unwrap(), 96);
+
+    }
+
+    #[test]
+    fn test_p_fraction() {
+        let config = Config {
+            percentiles: vec![9500, 9990],
+            span_sec: 1,
+            live_time_sec: 100,
+            ..Default::default()
+        };
+        assert!(config.clone().validate().is_ok());
+        assert_eq!(config.find(95).unwrap(), 1);
+        assert_eq!(config.find_hundredths(9990).unwrap(), 2);
+        assert!(config.find(99).is_err());
+        let mut h = Histogram::new(config);
+        h.append(0);
+        h.append(10000);
+        for x in 1..10000 {
+            h.append(x);
+        }
+        assert_eq!(h.sample_count_p(1 /* by index */).unwrap(), 9992);
+        assert_eq!(h.sample_count_p(0 /* by index */).unwrap(), 9502);
+        assert_eq!(h.average_p(1).unwrap(), 4999);
+        assert!(Config { percentiles: vec![10000], ..Default::default() }.validate().is_err());
+        assert!(Config { percentiles: vec![5000], ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_with_start() {
+        let start = Instant::now() - Duration::from_secs(10);
+        let mut h = Histogram::with_start(Config::default(), start);
+        h.append(5);
+        assert_eq!(h.buckets(), 1);
+        assert!(h.buckets.front().unwrap().time >= 10);
+        assert_eq!(h.median(), 5);
+        assert_eq!(h.sample_count(), 1);
+    }
+
+    #[test]
+    fn test_empty_buckets() {
+        let mut h = Histogram::new(Config::default());
+        h.append(1);
+        assert!(h.empty_buckets().is_empty());
+        // no gap filling yet, emulate an idle span
+        h.buckets.push_front(Bucket::new(2));
+        h.buckets.push_front(Bucket::new(4));
+        h.start -= Duration::from_secs(6);
+        h.append(1);
+        assert_eq!(h.empty_buckets(), vec![4, 2]);
+        assert_eq!(h.sample_count(), 2);
+    }
+
+    #[test]
+    fn test_evict_by_age() {
+        let mut h = Histogram::new(Config { live_time_sec: 10, ..Default::default() });
+        h.append(1000);
+        h.start -= Duration::from_secs(5);
+        h.append(1);
+        h.start -= Duration::from_secs(15);
+        h.append(5);
+        // both older buckets expired at once, the range drops their extremes
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(h.range.min_max, (5, 5));
+        assert_eq!(h.sample_count(), 1);
+    }
+
+    #[test]
+    fn test_reset_lifetime() {
+        let mut h = Histogram::new(Config { live_time_sec: 10, ..Default::default() });
+        h.append(1000);
+        h.start -= Duration::from_secs(20);
+        h.append(5);
+        h.append(6);
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(h.median(), 5);
+        assert_eq!(h.max_lt(), 1000);
+        h.reset_lifetime();
+        assert_eq!(h.max_lt(), 6);
+        assert_eq!(h.min_lt(), 5);
+        assert_eq!(h.sample_count(), 2);
+    }
+
+    #[test]
+    fn test_try_append() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.try_append(10).unwrap();
+        h.try_append(20).unwrap();
+        h.buckets.front_mut().unwrap().scale[0].count = u64::MAX - 1;
+        h.try_append(15).unwrap();
+        let buckets = h.buckets.clone();
+        let range = h.range.clone();
+        assert_eq!(h.try_append(15), Err(HistogramError::Overflow));
+        assert_eq!(h.buckets, buckets);
+        assert_eq!(h.range, range);
+
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append(10);
+        h.append(20);
+        h.buckets.front_mut().unwrap().scale[1].power = u64::MAX;
+        h.buckets.front_mut().unwrap().scale[1].sum = u64::MAX - 1;
+        // out of band value do not touch the percentile scale
+        h.try_append(20).unwrap();
+        assert_eq!(h.try_append(15), Err(HistogramError::Overflow));
+        assert_eq!(h.sample_count(), 3);
+
+        // a future dated newest bucket, the sample goes to the older bucket of now
+        let mut h = Histogram::new(Config::default());
+        h.append(1);
+        h.append_at(100, 5);
+        h.try_append(3).unwrap();
+        assert_eq!(h.buckets[1].scale[0].count, 2);
+        h.buckets[1].scale[0].count = u64::MAX;
+        assert_eq!(h.try_append(3), Err(HistogramError::Overflow));
+        assert_eq!(h.buckets[0].scale[0].count, 1);
+
+        // the totals and the lifetime of all buckets saturate before a bucket
+        for lifetime in [false, true] {
+            let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+            h.append(10);
+            h.append(20);
+            let totals = if lifetime { &mut h.lifetime } else { &mut h.totals };
+            totals[1].power = u64::MAX;
+            totals[1].sum = u64::MAX - 1;
+            let buckets = h.buckets.clone();
+            assert_eq!(h.try_append(15), Err(HistogramError::Overflow));
+            assert_eq!(h.buckets, buckets);
+            let totals = if lifetime { &mut h.lifetime } else { &mut h.totals };
+            totals[0].count = u64::MAX;
+            assert_eq!(h.try_append(20), Err(HistogramError::Overflow));
+        }
+
+        // the clamped value is checked, not the raw one
+        let mut h = Histogram::new(Config { max_valid_value: Some(100), clamp_invalid: true, ..Default::default() });
+        h.append(10);
+        h.buckets[0].scale[0].power = u64::MAX;
+        h.buckets[0].scale[0].sum = u64::MAX - 150;
+        h.try_append(1000).unwrap();
+        assert_eq!(h.buckets[0].scale[0].count, 2);
+        assert_eq!(h.try_append(1000), Err(HistogramError::Overflow));
+        // a rejected value touches no scale
+        let mut h = Histogram::new(Config { max_valid_value: Some(100), ..Default::default() });
+        h.append(10);
+        h.buckets[0].scale[0].power = u64::MAX;
+        h.buckets[0].scale[0].sum = u64::MAX - 150;
+        h.try_append(1000).unwrap();
+        assert_eq!((h.sample_count(), h.rejected_count()), (1, 1));
+    }
+
+    #[test]
+    fn test_merge() {
+        let config = Config { percentiles: vec![9000], live_time_sec: 100, ..Default::default() };
+        let mut a = Histogram::with_start(config.clone(), Instant::now() - Duration::from_secs(50));
+        let mut b = Histogram::with_start(config.clone(), Instant::now() - Duration::from_secs(7));
+        for x in 0..5 {
+            a.append(10 + x);
+            b.append(20 + x);
+            b.append(30 + x);
+            a.start -= Duration::from_secs(3);
+            b.start -= Duration::from_secs(3);
+        }
+        a.append(1);
+        assert_eq!(a.buckets(), 6);
+        assert_eq!(b.buckets(), 5);
+        let count = a.sample_count() + b.sample_count();
+        let count_p = a.sample_count_p(90).unwrap() + b.sample_count_p(90).unwrap();
+        a.merge(&b).unwrap();
+        assert_eq!(a.sample_count(), count);
+        assert_eq!(a.sample_count_p(90).unwrap(), count_p);
+        // out of phase by 50 - 7 seconds, but same age buckets are folded together
+        assert_eq!(a.buckets(), 6);
+        assert_eq!(a.buckets[1].scale[0].count, 3);
+        assert_eq!(a.median(), 17);
+        assert_eq!(a.max_lt(), 34);
+
+        let other = Histogram::new(Config::default());
+        assert_eq!(a.merge(&other), Err(HistogramError::ConfigMismatch));
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut h = Histogram::new(Config::default());
+        h.extend(0..101);
+        assert_eq!(h.sample_count(), 101);
+        assert_eq!(h.average(), 50);
+        h.extend(vec![200, 300]);
+        assert_eq!(h.sample_count(), 103);
+        assert_eq!(h.max_lt(), 300);
+        h.extend_from([1000u64]);
+        assert_eq!(h.sample_count(), 104);
+    }
+
+    #[test]
+    fn test_percentile_confidence() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        assert_eq!(h.percentile_confidence(90), None);
+        h.append(0);
+        h.append(1000);
+        for x in 1..1000 {
+            h.append(x);
+        }
+        let dense = h.percentile_confidence(90).unwrap();
+        assert!(dense > 0.9 && dense <= 1f64);
+        assert_eq!(h.percentile_confidence(99), None);
+
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append(0);
+        h.append(1000);
+        // far out of band
+        for _ in 0..100 {
+            h.append(1000);
+        }
+        h.append(500);
+        let sparse = h.percentile_confidence(90).unwrap();
+        assert!(sparse < 0.1);
+    }
+
+    #[test]
+    fn test_append_at() {
+        let mut h = Histogram::new(Config { live_time_sec: 10, ..Default::default() });
+        h.append_at(20, 1);
+        h.append_at(5, 2);
+        h.append_at(12, 3);
+        h.append_at(13, 4);
+        h.append_at(21, 5);
+        let times: Vec<u32> = h.buckets.iter().map(|b| b.time).collect();
+        assert_eq!(times, vec![20, 12]);
+        assert_eq!(h.sample_count(), 4);
+        h.append_at(40, 6);
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(h.median(), 6);
+    }
+
+    #[test]
+    fn test_append_at_system() {
+        let mut h = Histogram::new(Config::default());
+        let epoch = h.epoch();
+        h.append_at_system(epoch - Duration::from_secs(5), 1);
+        h.append_at_system(epoch, 2);
+        h.append_at_system(epoch + Duration::from_secs(3), 3);
+        h.append_at_system(epoch + Duration::from_secs(7), 4);
+        let times: Vec<u32> = h.buckets.iter().map(|b| b.time).collect();
+        assert_eq!(times, vec![7, 3, 0]);
+        assert_eq!(h.buckets.back().unwrap().scale[0].count, 2);
+    }
+
+    #[test]
+    fn test_oldest_newest_time() {
+        let mut h = Histogram::new(Config { span_sec: 2, ..Default::default() });
+        assert_eq!(h.oldest_time(), None);
+        assert_eq!(h.newest_time(), None);
+        h.append_at(1, 1);
+        assert_eq!(h.oldest_time(), Some(1));
+        assert_eq!(h.newest_time(), Some(1));
+        h.append_at(3, 1);
+        h.append_at(4, 1);
+        h.append_at(9, 1);
+        assert_eq!(h.buckets(), 3);
+        assert_eq!(h.oldest_time(), Some(1));
+        assert_eq!(h.newest_time(), Some(9));
+    }
+
+    #[test]
+    fn test_scale_mismatch() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append_at(0, 0);
+        h.append_at(0, 100);
+        h.append_at(0, 50);
+        // as if the config changed after the first bucket
+        Arc::make_mut(&mut h.config).percentiles.push(9500);
+        assert_eq!(h.sample_count_p(95).unwrap(), 0);
+        assert_eq!(h.average_p(95).unwrap(), 0);
+        h.append_at(5, 50);
+        assert_eq!(h.sample_count_p(95).unwrap(), 1);
+        assert_eq!(h.average_p(95).unwrap(), 50);
+        assert_eq!(h.sample_count_p(90).unwrap(), 3);
+        assert!(h.percentile_confidence(95).is_some());
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut h = Histogram::new(Config::default());
+        for x in 0..10 {
+            h.append_at(x * 2, 100 - x as u64);
+            h.append_at(x * 2, 200 - x as u64);
+        }
+        assert_eq!(h.buckets(), 10);
+        h.trim(3);
+        assert_eq!(h.buckets(), 3);
+        assert_eq!(h.oldest_time(), Some(14));
+        assert_eq!(h.range.min_max, (91, 193));
+        assert_eq!(h.range_lifetime.min_max, (91, 200));
+        assert_eq!(h.sample_count(), 6);
+        h.trim(5);
+        assert_eq!(h.buckets(), 3);
+    }
+
+    #[test]
+    fn test_time() {
+        let mut h = Histogram::new(Config { time_unit: TimeUnit::Millis, ..Default::default() });
+        let r = h.time(|| {
+            std::thread::sleep(Duration::from_millis(20));
+            7
+        });
+        assert_eq!(r, 7);
+        assert_eq!(h.sample_count(), 1);
+        assert!((20..1000).contains(&h.average()));
+        assert_eq!(TimeUnit::Nanos.convert(Duration::from_micros(3)), 3000);
+        assert_eq!(TimeUnit::Micros.convert(Duration::from_millis(3)), 3000);
+        assert_eq!(TimeUnit::Nanos.convert(Duration::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_scale_p() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append_at(0, 0);
+        h.append_at(0, 100);
+        h.append_at(0, 50);
+        h.append_at(3, 40);
+        h.append_at(3, 99);
+        let s = h.scale_p(90).unwrap();
+        assert_eq!(s, Scale { sum: 90, power: 0, count: 3 });
+        assert_eq!(s.avg(), h.average_p(90).unwrap());
+        assert!(matches!(h.scale_p(99), Err(HistogramError::UnknownPercentile(_))));
+    }
+
+    #[test]
+    fn test_top_n() {
+        let mut h = Histogram::new(Config { top_n: Some(100), ..Default::default() });
+        assert_eq!(h.top_n_average(), None);
+        for x in 1..1001u32 {
+            h.append_at(x % 10 * 2, (x * 7919 % 1000 + 1) as u64);
+        }
+        assert_eq!(h.sample_count(), 1000);
+        assert_eq!(h.buckets(), 10);
+        assert!(h.buckets.iter().all(|b| b.top.len() == 100));
+        assert_eq!(h.top_n_average(), Some(950));
+        assert_eq!(Histogram::new(Config::default()).top_n_average(), None);
+    }
+
+    #[test]
+    fn test_time_roll() {
+        let Some(start) = Instant::now().checked_sub(Duration::from_secs(u32::MAX as u64 + 10)) else {
+            return; // platform can't go back that far
+        };
+        let mut h = Histogram::with_start(Config::default(), start);
+        let epoch = h.epoch();
+        h.append(1);
+        h.append(2);
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(h.newest_time(), Some(u16::MAX as u32));
+        assert!(h.epoch() > epoch);
+        h.start -= Duration::from_secs(5);
+        h.append(3);
+        // no clamp at u32::MAX, so samples are not merged into one bucket
+        assert_eq!(h.buckets(), 2);
+        assert_eq!(h.newest_time(), Some(u16::MAX as u32 + 5));
+        assert_eq!(h.sample_count(), 3);
+
+        let mut h = Histogram::new(Config::default());
+        h.append_at(100, 1);
+        h.append_at(200, 1);
+        h.roll(150);
+        assert_eq!(h.oldest_time(), Some(0));
+        assert_eq!(h.newest_time(), Some(50));
+    }
+
+    #[test]
+    fn test_downsample() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append_at(0, 0);
+        h.append_at(0, 1000);
+        for x in 0..1000u32 {
+            h.append_at(x % 20 * 2, (x * 7 % 1000) as u64);
+        }
+        assert_eq!(h.buckets(), 20);
+        let d = h.downsample(5);
+        assert_eq!(d.buckets(), 5);
+        let times: Vec<u32> = d.buckets.iter().map(|b| b.time).collect();
+        assert_eq!(times, vec![32, 24, 16, 8, 0]);
+        assert_eq!(d.sample_count(), h.sample_count());
+        assert_eq!(d.sample_count_p(90), h.sample_count_p(90));
+        assert_eq!(d.average_p(90), h.average_p(90));
+        assert_eq!(d.average(), h.average());
+        assert_eq!(h.downsample(50).buckets(), 20);
+    }
+
+    #[test]
+    fn test_quantile() {
+        let mut h = Histogram::new(Config::default());
+        assert_eq!(h.quantile(0.5), Err(HistogramError::InsufficientSamples));
+        for x in 0..101 {
+            h.append(x);
+        }
+        assert_eq!(h.quantile(0.5), Ok(50));
+        assert_eq!(h.quantile(0f64), Ok(0));
+        assert_eq!(h.quantile(1f64), Ok(100));
+        assert!((94..=96).contains(&h.quantile(0.95).unwrap()));
+        assert_eq!(h.quantile(1.5), Err(HistogramError::UnknownPercentile("1.5 is out of 0..=1".to_string())));
+    }
+
+    #[test]
+    fn test_quantile_with_bounds() {
+        let mut h = Histogram::new(Config::default());
+        for x in 0..100 {
+            h.append_at(0, 100 + x % 10);
+            h.append_at(5, 100 + x % 10);
+        }
+        let (low, point, high) = h.quantile_with_bounds(0.5).unwrap();
+        assert_eq!(Ok(point), h.quantile(0.5));
+        assert!(low >= 100 && high <= 109);
+        // wide bucket
+        h.append_at(10, 0);
+        h.append_at(10, 10000);
+        for _ in 0..200 {
+            h.append_at(10, 5000);
+        }
+        let (low, point, high) = h.quantile_with_bounds(0.5).unwrap();
+        assert_eq!(Ok(point), h.quantile(0.5));
+        assert!(low <= 100 && high == 10000);
+        assert!(low <= point && point <= high);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000, 9990], ..Default::default() });
+        assert_eq!(h.snapshot(), Snapshot { count: 0, mean: 0, min: 0, max: 0, median: 0, percentiles: vec![(9000, 0), (9990, 0)] });
+        h.append(0);
+        h.append(100);
+        for x in 1..100 {
+            h.append(x);
+        }
+        let s = h.snapshot();
+        assert_eq!((s.count, s.mean, s.min, s.max, s.median), (101, 50, 0, 100, 50));
+        assert_eq!(s.percentiles, vec![(9000, h.average_p(90).unwrap()), (9990, h.average_p(1).unwrap())]);
+    }
+
+    #[test]
+    fn test_scaled() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append(0);
+        h.append(100_000);
+        for x in 1..100 {
+            h.append(x * 1000);
+        }
+        let s = h.scaled(0.001);
+        assert_eq!(s.sample_count(), h.sample_count());
+        assert_eq!(s.average(), h.average() / 1000);
+        assert_eq!(s.average_p(90).unwrap(), h.average_p(90).unwrap() / 1000);
+        assert_eq!(s.range.min_max, (0, 100));
+        assert_eq!(s.max_lt(), 100);
+        assert_eq!(h.scaled(2f64).average(), h.average() * 2);
+
+        let mut h = Histogram::new(Config::default());
+        h.append(u64::MAX);
+        h.append(u64::MAX);
+        let s = h.scaled(0.5);
+        assert_eq!(s.average(), u64::MAX / 2 + 1);
+        assert_eq!(h.scaled(4f64).max_lt(), u64::MAX);
+    }
+
+    #[test]
+    fn test_min_samples_for_percentile() {
+        let mut h = Histogram::new(Config {
+            percentiles: vec![9900],
+            min_samples_for_percentile: 100,
+            ..Default::default()
+        });
+        for x in 0..50 {
+            h.append(x);
+        }
+        assert_eq!(h.average_p(99), Err(HistogramError::InsufficientSamples));
+        assert_eq!(h.quantile(0.5), Err(HistogramError::InsufficientSamples));
+        for x in 0..100 {
+            h.append(x);
+        }
+        assert!(h.average_p(99).is_ok());
+        assert!(h.quantile(0.5).is_ok());
+
+        // the default of 1 keeps the 0 of an empty band
+        let h = Histogram::new(Config { percentiles: vec![9900], ..Default::default() });
+        assert_eq!(h.average_p(99), Ok(0));
+        assert_eq!(h.quantile(0.5), Err(HistogramError::InsufficientSamples));
+    }
+
+    #[test]
+    fn test_compare_percentiles() {
+        let config = Config { percentiles: vec![9000, 9900], ..Default::default() };
+        let mut h = Histogram::new(config.clone());
+        h.extend([0, 100]);
+        h.extend(1..100);
+        let mut baseline = h.snapshot();
+        baseline.percentiles.retain(|(p, _)| *p == 9000);
+        baseline.percentiles.push((9500, 1));
+        let mut h = Histogram::new(config);
+        h.extend([0, 200]);
+        h.extend((1..100).map(|x| x * 2));
+        let drift = h.compare_percentiles(&baseline);
+        assert_eq!(drift, vec![(9000, h.average_p(90).unwrap() as i64 - baseline.percentiles[0].1 as i64)]);
+        assert!(drift[0].1 > 40);
+        assert_eq!(h.compare_percentiles(&h.snapshot()), vec![(9000, 0), (9900, 0)]);
+    }
+
+    #[test]
+    fn test_clone_shares_config() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append(1);
+        let mut c = h.clone();
+        assert!(Arc::ptr_eq(&h.config, &c.config));
+        assert_eq!(c.config, h.config);
+        c.append(3);
+        assert_eq!(h.sample_count(), 1);
+        assert_eq!(c.sample_count(), 2);
+        assert_eq!(c.average(), 2);
+        assert_eq!(h.average(), 1);
+    }
+
+    #[test]
+    fn test_recorded_values() {
+        let mut h = Histogram::new(Config::default());
+        for x in 0..10 {
+            h.append_at(x * 2, 10 * x as u64);
+            h.append_at(x * 2, 10 * x as u64 + 4);
+            h.append_at(x * 2, 10 * x as u64 + 5);
+            h.append_at(x * 2 + 1, 10 * x as u64 + 6);
+        }
+        h.append_at(30, 11);
+        h.append_at(30, 13);
+        let values = h.to_recorded_values();
+        assert_eq!(values.first(), Some(&(3, 4)));
+        assert_eq!(values[1], (12, 2));
+        assert_eq!(values.len(), 11);
+        let r = Histogram::from_recorded_values(Config::default(), &values);
+        assert_eq!(r.sample_count(), h.sample_count());
+        assert!(r.average().abs_diff(h.average()) <= 1);
+        assert_eq!(r.buckets(), 1);
+    }
+
+    #[test]
+    fn test_averages_p() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000, 9500, 9900], ..Default::default() });
+        h.extend([0, 1000]);
+        h.extend(1..1000);
+        let all = h.averages_p(&[90, 95, 99, 0]).unwrap();
+        assert_eq!(all, vec![
+            (90, h.average_p(90).unwrap()),
+            (95, h.average_p(95).unwrap()),
+            (99, h.average_p(99).unwrap()),
+            (0, h.average_p(90).unwrap()),
+        ]);
+        assert!(matches!(h.averages_p(&[90, 97]), Err(HistogramError::UnknownPercentile(_))));
+        assert_eq!(h.averages_p(&[]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_mean() {
+        let mut h = Histogram::new(Config::default());
+        assert_eq!(h.mean(), None);
+        assert_eq!(h.average(), 0);
+        // skewed, unlike the middle of the range
+        h.extend([0, 0, 0, 100]);
+        assert_eq!(h.mean(), Some(25));
+        assert_eq!(h.average(), 25);
+        assert_eq!(h.median(), 50);
+        h.extend([u64::MAX, u64::MAX]);
+        assert_eq!(h.mean(), Some(((2 * u64::MAX as u128 + 100) / 6) as u64));
+    }
+
+    #[test]
+    fn test_count_between() {
+        let mut h = Histogram::new(Config::default());
+        assert_eq!(h.count_between(0, 100), 0);
+        h.extend(0..101);
+        assert!((50..=51).contains(&h.count_between(25, 75)));
+        assert_eq!(h.count_between(0, 100), 101);
+        assert_eq!(h.count_between(0, u64::MAX), h.sample_count());
+        assert_eq!(h.count_between(200, 300), 0);
+        assert_eq!(h.count_between(75, 25), 0);
+        assert_eq!(h.count_between(25, 75), h.sample_count() - h.count_below(25) - h.count_above(75));
+    }
+
+    #[test]
+    fn test_prune_sparse() {
+        let mut h = Histogram::new(Config::default());
+        for x in 0..5 {
+            h.append_at(x * 10, 1000 + x as u64);
+            for _ in 0..3 {
+                h.append_at(x * 10 + 5, 10 + x as u64);
+            }
+        }
+        h.append_at(60, 1);
+        assert_eq!(h.buckets(), 11);
+        assert_eq!(h.sample_count(), 21);
+        h.prune_sparse(2);
+        assert_eq!(h.buckets(), 6);
+        assert_eq!(h.sample_count(), 16);
+        assert_eq!(h.range.min_max, (1, 14));
+        assert_eq!(h.newest_time(), Some(60));
+        h.prune_sparse(2);
+        assert_eq!(h.buckets(), 6);
+    }
+
+    #[test]
+    fn test_incremental_totals() {
+        let mut a = Scale { sum: u64::MAX - 1, power: 1, count: 3 };
+        let b = Scale { sum: 5, power: 0, count: 1 };
+        a.add(&b);
+        assert_eq!(a, Scale { sum: 4, power: 2, count: 4 });
+        a.sub(&b);
+        assert_eq!(a, Scale { sum: u64::MAX - 1, power: 1, count: 3 });
+
+        let mut h = Histogram::new(Config { percentiles: vec![9000, 9900], live_time_sec: 10, ..Default::default() });
+        for t in 0..500u32 {
+            for x in 0..20u64 {
+                h.append_at(t, (t as u64 * 37 + x * 101) % 1000);
+            }
+            assert_eq!(h.totals, h.fold_scales());
+        }
+        assert!(h.buckets() <= 6);
+        assert_eq!(h.average_p(90).unwrap(), h.fold_scale(1).avg());
+        assert_eq!(h.average_p(99).unwrap(), h.fold_scale(2).avg());
+        assert_eq!(h.mean(), Some(h.fold_scale(0).avg()));
+        h.trim(2);
+        assert_eq!(h.totals, h.fold_scales());
+    }
+
+    #[test]
+    fn test_rounding() {
+        let mut down = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        let mut nearest = Histogram::new(Config { percentiles: vec![9000], rounding: Rounding::RoundNearest, ..Default::default() });
+        for v in [49, 50, 49, 50] {
+            down.append(v);
+            nearest.append(v);
+        }
+        assert_eq!(down.mean(), Some(49));
+        assert_eq!(nearest.mean(), Some(50));
+        assert_eq!(down.average_p(90).unwrap(), 49);
+        assert_eq!(nearest.average_p(90).unwrap(), 50);
+        assert_eq!(nearest.snapshot().mean, 50);
+        assert_eq!(Scale { sum: 0, power: 0, count: 0 }.avg_rounded(Rounding::RoundNearest), 0);
+        assert!(nearest.quantile(0.5).unwrap() <= 50);
+    }
+
+    #[test]
+    fn test_merge_bucket() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], span_sec: 5, live_time_sec: 60, ..Default::default() });
+        h.append_at(10, 100);
+        let mut b = Bucket::new(12);
+        b.scale[0] = Scale { sum: 600, power: 0, count: 3 };
+        b.scale.push(Scale { sum: 400, power: 0, count: 1 });
+        b.range = Range { min_max: (50, 400) };
+        h.merge_bucket(b.clone()).unwrap();
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(h.sample_count(), 4);
+        assert_eq!(h.mean(), Some(175));
+        assert_eq!((h.range.min(), h.range.max()), (50, 400));
+        assert_eq!(h.max_lt(), 400);
+        assert_eq!(h.totals, h.fold_scales());
+
+        b.time = 30;
+        h.merge_bucket(b.clone()).unwrap();
+        assert_eq!(h.buckets(), 2);
+        assert_eq!(h.newest_time(), Some(30));
+        assert_eq!(h.sample_count(), 7);
+
+        // short of the percentile scales is left out of the percentiles
+        b.time = 50;
+        b.scale.pop();
+        let p90 = h.sample_count_p(90).unwrap();
+        h.merge_bucket(b.clone()).unwrap();
+        assert_eq!(h.sample_count(), 10);
+        assert_eq!(h.sample_count_p(90).unwrap(), p90);
+        assert!(!h.buckets[0].has_percentiles);
+        b.scale.push(Scale { sum: 400, power: 0, count: 1 });
+        b.scale.push(Scale { sum: 400, power: 0, count: 1 });
+        assert_eq!(h.merge_bucket(b), Err(HistogramError::ConfigMismatch));
+        assert_eq!(h.sample_count(), 10);
+    }
+
+    #[test]
+    fn test_tail_retention() {
+        let config = Config { percentiles: vec![9900], span_sec: 5, live_time_sec: 20, ..Default::default() };
+        let mut plain = Histogram::new(config.clone());
+        let mut tail = Histogram::new(Config { tail_retention: Some(3), ..config });
+        for h in [&mut plain, &mut tail] {
+            h.append_at(0, 10);
+            h.append_at(10, 5000);
+            for t in (20..50).step_by(5) {
+                h.append_at(t, 10 + t as u64);
+            }
+        }
+        // the time 10 spike is older than the live time
+        assert_eq!(plain.oldest_time(), Some(20));
+        assert_eq!(plain.range.max(), 55);
+        assert_eq!(tail.oldest_time(), Some(10));
+        assert_eq!(tail.range.max(), 5000);
+        assert_eq!(tail.sample_count(), plain.sample_count() + 1);
+        assert_eq!(tail.totals, tail.fold_scales());
+
+        // kept up to the 3 times of the live time
+        tail.append_at(70, 1);
+        assert_eq!((tail.buckets(), tail.oldest_time()), (2, Some(10)));
+        tail.append_at(76, 1);
+        assert_eq!((tail.buckets(), tail.oldest_time()), (2, Some(70)));
+        assert_eq!(tail.range.max(), 1);
+    }
+
+    #[test]
+    fn test_append_many_at() {
+        let config = Config { percentiles: vec![9000, 9900], span_sec: 5, live_time_sec: 3600, ..Default::default() };
+        let mut samples: Vec<(u32, u64)> = (0..10_000u64)
+            .map(|i| (((i * 7919) % 20_000) as u32, (i * 104_729) % 5000))
+            .collect();
+        let mut bulk = Histogram::new(config.clone());
+        bulk.append_many_at(&samples);
+        samples.sort_by_key(|(time, _)| *time);
+        let mut each = Histogram::new(config);
+        for (time, value) in &samples {
+            each.append_at(*time, *value);
+        }
+        assert!(bulk.sample_count() < 10_000);
+        assert_eq!(bulk.buckets, each.buckets);
+        assert_eq!(bulk.snapshot(), each.snapshot());
+        assert_eq!(bulk.totals, bulk.fold_scales());
+
+        // of the buckets inserted out of order the older one spans past the start of the newer
+        let config = Config { span_sec: 5, live_time_sec: 3600, ..Default::default() };
+        let mut bulk = Histogram::new(config.clone());
+        for (time, value) in [(7, 1), (4, 2), (20, 3), (12, 4)] {
+            bulk.append_at(time, value);
+        }
+        let mut each = bulk.clone();
+        let samples: Vec<(u32, u64)> = (0..30u32).map(|t| (t, t as u64 * 10)).collect();
+        bulk.append_many_at(&samples);
+        for (time, value) in &samples {
+            each.append_at(*time, *value);
+        }
+        assert_eq!(bulk.buckets, each.buckets);
+        assert_eq!(bulk.totals, bulk.fold_scales());
+    }
+
+    #[test]
+    fn test_tail() {
+        let config = Config { percentiles: vec![9500], ..Default::default() };
+        let mut both = Histogram::new(config.clone());
+        let mut upper = Histogram::new(Config { tail: Tail::Upper, ..config.clone() });
+        let mut lower = Histogram::new(Config { tail: Tail::Lower, ..config });
+        // skewed: most samples fast, a few slow
+        for h in [&mut both, &mut upper, &mut lower] {
+            h.append(1000);
+            h.append(0);
+            for i in 0..1000u64 {
+                h.append(if i % 100 == 0 { 960 + i / 100 } else { 10 + i % 50 });
+            }
+        }
+        let r = Range { min_max: (0, 1000) };
+        assert!(r.check_in(Tail::Both, 0f32, 9500, 30) && !r.check_in(Tail::Both, 0f32, 9500, 990));
+        assert!(!r.check_in(Tail::Upper, 0f32, 9500, 30) && r.check_in(Tail::Upper, 0f32, 9500, 990));
+        assert!(r.check_in(Tail::Lower, 0f32, 9500, 30) && !r.check_in(Tail::Lower, 0f32, 9500, 990));
+        assert!(both.average_p(95).unwrap() < 100);
+        assert!(upper.average_p(95).unwrap() >= 960);
+        assert!(lower.average_p(95).unwrap() < 50);
+        assert_eq!(upper.sample_count_p(95).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_percentile_of_max() {
+        let mut h = Histogram::new(Config::default());
+        assert_eq!(h.max(), None);
+        assert_eq!(h.percentile_of_max(), None);
+        for i in 0..999u64 {
+            h.append_at(0, 10 + i % 20);
+        }
+        h.append_at(5, 1_000_000);
+        assert_eq!(h.max(), Some(1_000_000));
+        assert_eq!(h.percentile_of_max(), Some(99));
+        assert_eq!(h.percentile_rank(10), Some(0));
+
+        let mut h = Histogram::new(Config::default());
+        for _ in 0..100 {
+            h.append(42);
+        }
+        assert_eq!(h.percentile_of_max(), Some(0));
+    }
+
+    #[test]
+    fn test_is_bimodal() {
+        let mut h = Histogram::new(Config { span_sec: 1, live_time_sec: 600, ..Default::default() });
+        assert_eq!(h.is_bimodal(0.5), None);
+        for t in 0..100u32 {
+            let base = if t % 2 == 0 { 100 } else { 900 };
+            for i in 0..10 {
+                h.append_at(t * 2, base + i);
+            }
+        }
+        assert_eq!(h.is_bimodal(0.5), Some(true));
+
+        let mut h = Histogram::new(Config { span_sec: 1, live_time_sec: 600, ..Default::default() });
+        for t in 0..100u32 {
+            let spread = 10 + 4 * t.abs_diff(50) as u64;
+            for i in 0..10 {
+                h.append_at(t * 2, 500 - spread + i * spread / 5);
+            }
+        }
+        assert_eq!(h.is_bimodal(0.5), Some(false));
+    }
+
+    #[test]
+    fn test_percentile_report() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000, 9900], ..Default::default() });
+        assert!(h.percentile_report().iter().all(|r| r.count == 0 && r.confidence == 0f64));
+        h.append(0);
+        h.append(1000);
+        for v in 0..1000 {
+            h.append(v);
+        }
+        let report = h.percentile_report();
+        assert_eq!(report.len(), 2);
+        for (r, p) in report.iter().zip([90u8, 99]) {
+            assert_eq!(r.average, h.average_p(p).unwrap());
+            assert_eq!(r.count, h.sample_count_p(p).unwrap());
+            assert_eq!(Some(r.confidence), h.percentile_confidence(p));
+        }
+        assert_eq!((report[0].percentile, report[1].percentile), (9000, 9900));
+        assert!(report[0].count < report[1].count);
+
+        // the upper band of p95 is expected to hold the slowest 5%
+        let mut both = Histogram::new(Config { percentiles: vec![9500], ..Default::default() });
+        let mut upper = Histogram::new(Config { percentiles: vec![9500], tail: Tail::Upper, ..Default::default() });
+        for h in [&mut both, &mut upper] {
+            h.append(0);
+            h.append(1000);
+            for v in 0..1001 {
+                h.append(v);
+            }
+        }
+        let (b, u) = (&both.percentile_report()[0], &upper.percentile_report()[0]);
+        assert_eq!(u.count, upper.sample_count_p(95).unwrap());
+        assert_eq!(Some(u.confidence), upper.percentile_confidence(95));
+        assert!(u.count * 10 < b.count);
+        // the band is fully covered, only damped for its size
+        assert_eq!(u.confidence, 1f64 - 1f64 / (u.count as f64 + 1f64).sqrt());
+        assert!(b.confidence > 0.9);
+    }
+
+    #[test]
+    fn test_range_merge() {
+        let mut r = Range::default();
+        r.merge(&Range::default());
+        assert_eq!(r, Range::default());
+        r.merge(&Range { min_max: (10, 20) });
+        assert_eq!(r.min_max, (10, 20));
+        r.merge(&Range::default());
+        r.merge(&Range { min_max: (15, 30) });
+        r.merge(&Range { min_max: (5, 5) });
+        assert_eq!(r.min_max, (5, 30));
+        r.merge(&Range { min_max: (0, u64::MAX) });
+        assert_eq!(r.min_max, (0, u64::MAX));
+    }
+
+    #[test]
+    fn test_percentiles_fidelity() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], span_sec: 5, live_time_sec: 60, ..Default::default() });
+        h.append_at(0, 10);
+        h.append_at(0, 20);
+        // of a config without the percentiles
+        let mut plain = Histogram::new(Config { span_sec: 5, live_time_sec: 60, ..Default::default() });
+        for _ in 0..3 {
+            plain.append_at(10, 15);
+        }
+        h.merge_bucket(plain.buckets[0].clone()).unwrap();
+        assert!(!h.buckets[0].has_percentiles);
+        h.append_at(10, 15);
+        assert_eq!(h.buckets[0].scale.len(), 1);
+        h.append_at(20, 12);
+        assert_eq!(h.sample_count(), 7);
+        // the loaded samples and the one appended after into the same bucket are left out
+        assert_eq!(h.sample_count_p(90).unwrap(), 2);
+        assert_eq!(h.average_p(90).unwrap(), 11);
+        assert_eq!(h.totals, h.fold_scales());
+        assert_eq!(h.percentile_report()[0].excluded, vec![10]);
+
+        let mut other = h.buckets[1].clone();
+        other.time = 20;
+        h.merge_bucket(other).unwrap();
+        assert_eq!(h.percentile_report()[0].excluded, vec![20, 10]);
+        assert_eq!(h.totals, h.fold_scales());
+    }
+
+    #[test]
+    fn test_mean_complete() {
+        let config = Config { span_sec: 5, live_time_sec: 60, ..Default::default() };
+        let mut h = Histogram::with_start(config.clone(), Instant::now() - Duration::from_secs(25));
+        h.append_at(0, 100);
+        h.append_at(10, 200);
+        // the front bucket of 20..=25 is filling right at the boundary
+        h.append_at(20, 1000);
+        assert_eq!(h.mean(), Some(433));
+        assert_eq!(h.mean_complete(), Some(150));
+
+        h.start -= Duration::from_secs(1);
+        assert_eq!(h.mean_complete(), Some(433));
+        assert_eq!(Histogram::new(config).mean_complete(), None);
+    }
+
+    #[test]
+    fn test_max_valid_value() {
+        let config = Config { percentiles: vec![9000], max_valid_value: Some(10_000), ..Default::default() };
+        let mut h = Histogram::new(config.clone());
+        let mut clamp = Histogram::new(Config { clamp_invalid: true, ..config });
+        for h in [&mut h, &mut clamp] {
+            for v in [100, u64::MAX, 200, 10_001, 300] {
+                h.append(v);
+            }
+            h.append_many_at(&[(0, u64::MAX)]);
+        }
+        assert_eq!(h.rejected_count(), 3);
+        assert_eq!(h.sample_count(), 3);
+        assert_eq!(h.range.min_max, (100, 300));
+        assert_eq!(h.mean(), Some(200));
+        assert_eq!(clamp.rejected_count(), 3);
+        assert_eq!(clamp.sample_count(), 6);
+        assert_eq!(clamp.range.min_max, (100, 10_000));
+    }
+
+    #[test]
+    fn test_append_duration() {
+        let mut h = Histogram::new(Config { time_unit: TimeUnit::Millis, ..Default::default() });
+        assert_eq!(h.mean_duration(), None);
+        for _ in 0..10 {
+            h.append_duration(Duration::from_millis(100));
+        }
+        h.append_duration(Duration::from_micros(100_999));
+        assert_eq!(h.mean(), Some(100));
+        assert_eq!(h.mean_duration(), Some(Duration::from_millis(100)));
+        assert_eq!(h.quantile_duration(0.5), Ok(Duration::from_millis(100)));
+        assert_eq!(TimeUnit::Nanos.duration(1500), Duration::from_nanos(1500));
+    }
+
+    #[test]
+    fn test_average_p_duration() {
+        let mut h = seeded(Config { percentiles: vec![9500], time_unit: TimeUnit::Millis, ..Default::default() }, (0, 100));
+        assert_eq!(h.average_p_duration(95), Ok(Duration::ZERO));
+        for ms in 0..=100 {
+            h.append_duration(Duration::from_millis(ms));
+        }
+        // the band of the 95% width centered in the range
+        assert_eq!(h.average_p_duration(95), Ok(Duration::from_millis(h.average_p(95).unwrap())));
+        assert_eq!(h.average_p_duration(95), Ok(Duration::from_millis(50)));
+        assert!(h.average_p_duration(99).is_err());
+    }
+
+    #[test]
+    fn test_buckets_with_systemtime() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], span_sec: 5, ..Default::default() });
+        h.append_at(0, 10);
+        h.append_at(0, 30);
+        h.append_at(60, 7);
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let b = h.buckets_with_systemtime(epoch);
+        assert_eq!(b.len(), 2);
+        assert_eq!(b[0].0, epoch + Duration::from_secs(60));
+        assert_eq!((b[0].1.count, b[0].1.mean, b[0].1.min, b[0].1.max), (1, 7, 7, 7));
+        assert_eq!(b[1].0, epoch);
+        assert_eq!((b[1].1.count, b[1].1.mean, b[1].1.median), (2, 20, 20));
+        assert_eq!(b[1].1.percentiles, vec![(9000, 10)]);
+        assert_eq!(h.buckets_with_systemtime(h.epoch())[0].0, h.epoch() + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_total_scale() {
+        let mut h = Histogram::new(Config { span_sec: 1, ..Default::default() });
+        assert_eq!(h.total_scale(), Scale { sum: 0, power: 0, count: 0 });
+        for t in 0..10u32 {
+            h.append_at(t * 2, t as u64 * 10);
+            h.append_at(t * 2, u64::MAX / 2);
+        }
+        let s = h.total_scale();
+        assert_eq!(s.count, 20);
+        assert_eq!(s.power, 5);
+        assert_eq!(Some(s.avg()), h.mean());
+        assert_eq!(s, h.fold_scale(0));
+    }
+
+    #[test]
+    fn test_percentile_changepoint() {
+        let config = Config { percentiles: vec![9500], span_sec: 5, live_time_sec: 600, max_valid_value: Some(100_000), ..Default::default() };
+        let mut h = seeded(config, (0, 3000));
+        assert_eq!(h.percentile_changepoint(95, 0.5), None);
+        for t in 1..20u32 {
+            for i in 0..50u64 {
+                h.append_at(t * 10, if t < 12 { i * 20 } else { 1000 + i * 40 });
+            }
+        }
+        assert_eq!(h.percentile_changepoint(95, 0.5), Some(120));
+        assert_eq!(h.percentile_changepoint(95, 0.9), None);
+        assert_eq!(h.percentile_changepoint(90, 0.5), None);
+    }
+
+    #[test]
+    fn test_split_at_time() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], span_sec: 5, live_time_sec: 600, ..Default::default() });
+        for t in 0..10u32 {
+            for i in 0..=t as u64 {
+                h.append_at(t * 10, t as u64 * 100 + i);
+            }
+        }
+        let (count, buckets) = (h.sample_count(), h.buckets());
+        // 42 is within the bucket begun at 40
+        let (older, newer) = h.split_at_time(42);
+        assert_eq!(older.sample_count() + newer.sample_count(), count);
+        assert_eq!(older.buckets() + newer.buckets(), buckets);
+        assert_eq!((older.oldest_time(), older.newest_time()), (Some(0), Some(40)));
+        assert_eq!(newer.oldest_time(), Some(50));
+        assert_eq!(older.range.min_max, (0, 404));
+        assert_eq!(newer.range.min_max, (500, 909));
+        assert_eq!(older.totals, older.fold_scales());
+        assert_eq!(older.config, newer.config);
+
+        let (older, newer) = newer.split_at_time(0);
+        assert_eq!((older.buckets(), newer.buckets()), (0, 5));
+        assert_eq!(older.mean(), None);
+    }
+
+    #[test]
+    fn test_suggest_config() {
+        for (target, window) in [(60, 3600), (10, 60), (100, 100), (1000, 60), (5, 1000), (10, 1)] {
+            let config = suggest_config(target, Duration::from_secs(window));
+            assert!(config.clone().validate().is_ok(), "{} {}", target, window);
+            let span = config.span_sec as u32;
+            let mut h = Histogram::new(config);
+            for t in 0..window as u32 * 2 {
+                h.append_at(t, 1);
+            }
+            let expected = target.min(window as usize / 2).max(2);
+            assert!(h.buckets().abs_diff(expected) <= 1, "{} {}: {} of {}s", target, window, h.buckets(), span);
+        }
+        assert_eq!(suggest_config(60, Duration::from_secs(3600)).span_sec, 59);
+        assert_eq!(suggest_config(5, Duration::from_secs(7200)).span_sec, 255);
+    }
+
+    #[test]
+    fn test_sample_count_past_u32() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append_n(7, u32::MAX);
+        h.append_n(7, u32::MAX);
+        h.append_n(9, 2);
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(h.buckets[0].scale[0].count, 2 * u32::MAX as u64 + 2);
+        assert_eq!(h.sample_count(), 2 * u32::MAX as u64 + 2);
+        assert_eq!(h.sample_count_p(90).unwrap(), h.sample_count());
+        assert_eq!(h.mean(), Some(7));
+
+        let mut s = Scale { sum: 0, power: 0, count: u64::MAX - 1 };
+        s.add(&Scale { sum: 1, power: 0, count: 5 });
+        assert_eq!(s.count, u64::MAX);
+
+        // the power past u32 is kept of the total
+        let mut s = Scale { sum: 0, power: u32::MAX as u64, count: 2 * u32::MAX as u64 };
+        s.add(&Scale { sum: 0, power: u32::MAX as u64, count: 2 * u32::MAX as u64 });
+        assert_eq!(s.power, 2 * u32::MAX as u64);
+        assert_eq!(s.avg(), u64::MAX / 2);
+        let mut s = Scale { sum: 0, power: 0, count: 1 };
+        s.set_total(u128::MAX);
+        assert_eq!(s.power, u64::MAX);
+    }
+
+    #[test]
+    fn test_log_line() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000, 9500, 9990], min_samples_for_percentile: 2, ..Default::default() });
+        assert_eq!(h.log_line(), "n=0");
+        h.append(100);
+        h.append(0);
+        for v in 0..99 {
+            h.append(v);
+        }
+        assert_eq!(h.log_line(), format!("p90={} p95={} p99.9={} n=101",
+            h.average_p(90).unwrap(), h.average_p(95).unwrap(), h.scale_by_id(3).avg()));
+        assert_eq!(h.log_line(), "p90=50 p95=50 p99.9=49 n=101");
+    }
+
+    #[test]
+    fn test_fold_bucket_unchecked() {
+        let config = Config { percentiles: vec![9000], span_sec: 5, live_time_sec: 60, ..Default::default() };
+        let mut remote = Histogram::new(config.clone());
+        for t in 0..10u32 {
+            for v in 0..20u64 {
+                remote.append_at(t * 6, v * t as u64);
+            }
+        }
+        let mut checked = Histogram::new(config.clone());
+        let mut unchecked = Histogram::new(config);
+        for h in [&mut checked, &mut unchecked] {
+            h.append_at(3, 500);
+            h.append_at(30, 1);
+        }
+        for b in remote.buckets.iter().rev() {
+            checked.merge_bucket(b.clone()).unwrap();
+            unchecked.fold_bucket_unchecked(b.clone());
+        }
+        assert_eq!(unchecked.buckets, checked.buckets);
+        assert_eq!(unchecked.range, checked.range);
+        assert_eq!(unchecked.range_lifetime, checked.range_lifetime);
+        assert_eq!(unchecked.totals, checked.totals);
+        assert_eq!(unchecked.range, unchecked.buckets_range());
+        assert_eq!(unchecked.snapshot(), checked.snapshot());
+    }
+
+    #[test]
+    fn test_resample_uniform() {
+        let mut h = Histogram::new(Config { live_time_sec: 600, ..Default::default() });
+        assert!(h.resample_uniform(10).is_empty());
+        for t in 0..20u32 {
+            for v in 0..=(t as u64 * 10) {
+                h.append_at(t * 3, 1000 + v * 7);
+            }
+        }
+        let samples = h.resample_uniform(5000);
+        assert_eq!(samples.len(), 5000);
+        let mean = samples.iter().sum::<u64>() / samples.len() as u64;
+        assert!(mean.abs_diff(h.mean().unwrap()) <= h.mean().unwrap() / 100, "{} {:?}", mean, h.mean());
+        assert!(samples.iter().all(|v| (h.range.min()..=h.range.max()).contains(v)));
+    }
+
+    #[test]
+    fn test_percentile_estimator() {
+        let config = Config { percentiles: vec![9000], live_time_sec: 600, ..Default::default() };
+        let mut band = Histogram::new(config.clone());
+        let mut weighted = Histogram::new(Config { estimator: PercentileEstimator::WeightedBuckets, ..config });
+        let mut values = vec![];
+        for t in 0..100u32 {
+            for i in 0..20u64 {
+                // 95% fast, the slow buckets are apart
+                values.push(if t % 20 == 19 { 1000 + i * 50 } else { i * 5 });
+                band.append_at(t * 2, *values.last().unwrap());
+                weighted.append_at(t * 2, *values.last().unwrap());
+            }
+        }
+        values.sort_unstable();
+        let truth = values[values.len() * 9 / 10];
+        let b = band.average_p(90).unwrap();
+        let w = weighted.average_p(90).unwrap();
+        assert!(w.abs_diff(truth) < b.abs_diff(truth), "{} {} {}", truth, b, w);
+        assert!(w.abs_diff(truth) <= 5, "{} {}", truth, w);
+        assert_eq!(weighted.snapshot().percentiles, vec![(9000, w)]);
+        assert_eq!(weighted.averages_p(&[90]).unwrap(), vec![(90, w)]);
+        assert_eq!(Histogram::new(Config { estimator: PercentileEstimator::WeightedBuckets, percentiles: vec![9000], ..Default::default() })
+            .average_p(90), Err(HistogramError::InsufficientSamples));
+    }
+
+    #[test]
+    fn test_warmup() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::OnceLock;
+
+        static SECS: AtomicU64 = AtomicU64::new(0);
+        static BASE: OnceLock<Instant> = OnceLock::new();
+        fn clock() -> Instant {
+            *BASE.get_or_init(Instant::now) + Duration::from_secs(SECS.load(Ordering::Relaxed))
+        }
+        let config = Config { percentiles: vec![9000], warmup_sec: 30, ..Default::default() };
+        let mut h = Histogram::with_clock(config, clock);
+        for v in 0..100 {
+            h.append(v);
+        }
+        let baseline = h.snapshot();
+        SECS.store(29, Ordering::Relaxed);
+        assert!(h.warming_up());
+        assert_eq!(h.mean(), None);
+        assert_eq!(h.average_p(90), Err(HistogramError::Warmup));
+        assert_eq!(h.averages_p(&[90]), Err(HistogramError::Warmup));
+        assert_eq!(h.quantile(0.5), Err(HistogramError::Warmup));
+        assert_eq!(h.sample_count(), 100);
+        let s = h.snapshot();
+        assert_eq!((s.count, s.mean, s.median, s.percentiles.clone()), (100, 0, 0, vec![(9000, 0)]));
+        assert_eq!(h.percentile_report()[0].average, 0);
+        assert_eq!(h.compare_percentiles(&baseline), vec![]);
+
+        SECS.store(30, Ordering::Relaxed);
+        assert!(!h.warming_up());
+        assert_eq!(h.mean(), Some(49));
+        assert!(h.average_p(90).is_ok());
+        assert!(h.quantile(0.5).is_ok());
+        let s = h.snapshot();
+        assert_eq!((s.mean, s.percentiles[0].1), (49, h.average_p(90).unwrap()));
+        assert_eq!(h.percentile_report()[0].average, s.percentiles[0].1);
+        assert_eq!(h.compare_percentiles(&s), vec![(9000, 0)]);
+        // the samples of the clock time
+        h.append(1000);
+        assert_eq!(h.newest_time(), Some(30));
+        assert!(!Histogram::new(Config::default()).warming_up());
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let config = Config { span_sec: 1, live_time_sec: 0, top_n: Some(2), ..Default::default() };
+        let mut h = Histogram::with_ring_buffer(config.clone());
+        let cap = h.buckets.capacity();
+        assert!(cap >= 1);
+        for t in 0..10u32 {
+            h.append_at(t * 2, 100 + t as u64);
+        }
+        assert_eq!(h.buckets.len(), 1);
+        assert_eq!(h.buckets.capacity(), cap);
+        assert_eq!(h.sample_count(), 1);
+        assert_eq!(h.range.min_max, (109, 109));
+        assert_eq!(h.totals[0].count, 1);
+        h.append_at(3, 1);
+        assert_eq!(h.sample_count(), 1);
+
+        let config = Config { span_sec: 1, live_time_sec: 4, ..config };
+        let mut h = Histogram::with_ring_buffer(config);
+        assert_eq!(h.ring, Some(6));
+        let cap = h.buckets.capacity();
+        let scales: Vec<usize> = (0..40u32).map(|t| {
+            h.append_at(t, t as u64);
+            h.buckets.iter().map(|b| b.scale.capacity()).sum()
+        }).collect();
+        assert_eq!(h.buckets.capacity(), cap);
+        assert!(h.buckets.len() <= 6);
+        assert_eq!(scales[39], scales[20]);
+        assert_eq!(h.totals[0].count, h.fold_scale(0).count);
+        assert_eq!(h.range.min_max, h.buckets_range().min_max);
+    }
+
+    #[test]
+    fn test_check_in_wide_range() {
+        let r = Range { min_max: (0, u64::MAX - 1) };
+        // (u64::MAX - 1) / 20 rounded up
+        let pp = (u64::MAX - 1) / 20 + 1;
+        assert!(r.check_in(Tail::Both, 0f32, 9000, pp));
+        assert!(!r.check_in(Tail::Both, 0f32, 9000, pp - 1));
+        assert!(r.check_in(Tail::Both, 0f32, 9000, u64::MAX - 1 - pp));
+        assert!(!r.check_in(Tail::Both, 0f32, 9000, u64::MAX - pp));
+        assert!(r.check_in(Tail::Both, 0f32, 10000, 0));
+        assert!(r.check_in(Tail::Upper, 0f32, 9999, u64::MAX - 1 - (u64::MAX - 1) / 10000));
+        assert!(!r.check_in(Tail::Upper, 0f32, 9999, u64::MAX - 2 - (u64::MAX - 1) / 10000));
+        assert!(r.check_in(Tail::Lower, 0f32, 5000, u64::MAX / 2));
+        assert!(!r.check_in(Tail::Lower, 0f32, 5000, u64::MAX / 2 + 1));
+    }
+
+    #[test]
+    fn test_merge_all() {
+        let config = Config { percentiles: vec![9000], live_time_sec: 100, ..Default::default() };
+        let shards: Vec<Histogram> = (0..5u64).map(|i| {
+            let mut h = Histogram::new(config.clone());
+            for x in 0..10 {
+                h.append(i * 100 + x);
+            }
+            h
+        }).collect();
+        let merged = Histogram::merge_all(&shards).unwrap();
+        assert_eq!(merged.sample_count(), 50);
+        assert_eq!((merged.range.min(), merged.range.max()), (0, 409));
+        assert_eq!(merged.sample_count_p(90).unwrap(), shards.iter().map(|h| h.sample_count_p(90).unwrap()).sum());
+
+        // the bucket times of the shards are kept
+        let config = Config { span_sec: 5, live_time_sec: 200, ..config };
+        let start = Instant::now() - Duration::from_secs(100);
+        let shards: Vec<Histogram> = (0..3u64).map(|i| {
+            let mut h = Histogram::with_start(config.clone(), start);
+            for t in 0..10u32 {
+                h.append_at(t * 10, i * 100 + t as u64);
+            }
+            h
+        }).collect();
+        let merged = Histogram::merge_all(&shards).unwrap();
+        let mut pairwise = shards[0].clone();
+        for h in &shards[1..] {
+            pairwise.merge(h).unwrap();
+        }
+        assert_eq!(merged.buckets(), 10);
+        assert_eq!(merged.buckets, pairwise.buckets);
+        assert_eq!((merged.oldest_time(), merged.newest_time()), (shards[0].oldest_time(), shards[0].newest_time()));
+        assert_eq!(merged.sample_count(), 30);
+
+        let other = Histogram::new(Config::default());
+        assert_eq!(Histogram::merge_all(&[shards[0].clone(), other]).err(), Some(HistogramError::ConfigMismatch));
+        assert!(matches!(Histogram::merge_all(&[]), Err(HistogramError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_percentile_stddev() {
+        let config = Config { percentiles: vec![9000], span_sec: 1, live_time_sec: 100, ..Default::default() };
+        // no band out of a growing range of the first bucket
+        let mut stable = seeded(config.clone(), (0, 100));
+        let mut noisy = seeded(config, (0, 1000));
+        assert_eq!(stable.percentile_stddev(90), None);
+        for t in 0..10u32 {
+            for v in 0..=100 {
+                stable.append_at(t * 2, v);
+                noisy.append_at(t * 2, if t % 2 == 0 { v } else { 900 + v });
+            }
+        }
+        assert_eq!(stable.percentile_stddev(50), None);
+        assert!(stable.percentile_stddev(90).unwrap() < 5f64);
+        assert!(noisy.percentile_stddev(90).unwrap() > 300f64);
+    }
+
+    #[test]
+    fn test_geometric_mean() {
+        let config = Config { span_sec: 1, live_time_sec: 100, ..Default::default() };
+        let mut derived = Histogram::new(config.clone());
+        let mut exact = Histogram::new(Config { log_scale: true, ..config });
+        assert_eq!(exact.geometric_mean(), None);
+        for h in [&mut derived, &mut exact] {
+            h.append_at(0, 1);
+            h.append_at(0, 100);
+            h.append_at(4, 10);
+            h.append_at(4, 1000);
+        }
+        // (1 * 100 * 10 * 1000) ^ 1/4 ~ 31.62
+        assert!((exact.geometric_mean().unwrap() - 31.62f64).abs() < 0.01);
+        // of the bucket averages sqrt(50 * 505) ~ 158.9
+        assert!((derived.geometric_mean().unwrap() - 158.9f64).abs() < 0.1);
+        assert!((exact.scaled(2f64).geometric_mean().unwrap() - 63.25f64).abs() < 0.01);
+
+        let config = Config { percentiles: vec![9000], span_sec: 1, live_time_sec: 100, log_scale: true, ..Default::default() };
+        let mut h = Histogram::new(config);
+        for v in [1, 100, 10, 1000, 900, 950] {
+            h.append_at(0, v);
+        }
+        h.append_at(4, 920);
+        // the band of the range as of each append holds 1, 10, 900, 950 and 920
+        assert_eq!((h.sample_count_p(90), h.average_p(90)), (Ok(5), Ok(556)));
+        let band = (1f64 * 10f64 * 900f64 * 950f64 * 920f64).powf(0.2);
+        assert!((h.geometric_average_p(90).unwrap() - band).abs() < 0.01);
+        let mut out = Vec::new();
+        h.encode(&mut out);
+        assert!((Histogram::decode(&out).unwrap().geometric_average_p(90).unwrap() - band).abs() < 0.01);
+        assert!((h.scaled(2f64).geometric_average_p(90).unwrap() - 2f64 * band).abs() < 0.01);
+        assert!(matches!(h.geometric_average_p(99), Err(HistogramError::UnknownPercentile(_))));
+        assert!(matches!(derived.geometric_average_p(90), Err(HistogramError::UnknownPercentile(_))));
+        let mut plain = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        plain.append(5);
+        assert!(matches!(plain.geometric_average_p(90), Err(HistogramError::InvalidConfig(_))));
+        assert_eq!(h.buckets[1].log_scales().len(), 2);
+        assert_eq!(h.buckets[1].squares().count, 6);
+    }
+
+    #[test]
+    fn test_peak_bucket() {
+        let mut h = Histogram::new(Config { span_sec: 1, live_time_sec: 100, ..Default::default() });
+        assert_eq!(h.peak_bucket(), None);
+        for (t, n) in [(0, 3), (2, 10), (4, 2), (6, 10), (8, 1)] {
+            h.record_n(t, 5, n);
+        }
+        assert_eq!(h.peak_bucket(), Some((6, 10)));
+        h.append_at(2, 5);
+        assert_eq!(h.peak_bucket(), Some((2, 11)));
+    }
+
+    #[test]
+    fn test_percentile_window() {
+        let config = Config { percentiles: vec![9000], span_sec: 1, live_time_sec: 10, ..Default::default() };
+        let mut live = seeded(config.clone(), (0, 1000));
+        let mut lifetime = seeded(Config { window: PercentileWindow::Lifetime, ..config }, (0, 1000));
+        for h in [&mut live, &mut lifetime] {
+            for v in 0..=100 {
+                h.append_at(0, v * 10);
+            }
+            for t in [4, 8, 60] {
+                for v in 0..=100 {
+                    h.append_at(t, v);
+                }
+            }
+        }
+        assert_eq!(live.buckets(), 1);
+        assert_eq!(live.sample_count(), lifetime.sample_count());
+        assert!(live.average_p(90).unwrap() <= 100);
+        // the evicted spikes of the first bucket are kept
+        assert!(lifetime.average_p(90).unwrap() > 100);
+        assert!(lifetime.sample_count_p(90).unwrap() < lifetime.scale_p(90).unwrap().count);
+
+        lifetime.reset_lifetime();
+        assert_eq!(lifetime.average_p(90).unwrap(), live.average_p(90).unwrap());
+    }
+
+    #[test]
+    fn test_bucket_snapshots() {
+        let mut h = Histogram::new(Config { span_sec: 1, live_time_sec: 100, ..Default::default() });
+        assert_eq!(h.bucket_snapshots().count(), 0);
+        h.append_at(0, 10);
+        h.append_at(0, 30);
+        h.append_at(4, 7);
+        h.append_at(8, 100);
+        h.append_at(8, 200);
+        h.append_at(8, 301);
+        let snapshots: Vec<(u32, BucketSnapshot)> = h.bucket_snapshots().collect();
+        assert_eq!(snapshots, vec![
+            (8, BucketSnapshot { count: 3, min: 100, max: 301, mean: 200 }),
+            (4, BucketSnapshot { count: 1, min: 7, max: 7, mean: 7 }),
+            (0, BucketSnapshot { count: 2, min: 10, max: 30, mean: 20 }),
+        ]);
+    }
+
+    #[test]
+    fn test_percentiles_monotonic() {
+        let config = Config { percentiles: vec![9900, 5000], ..Default::default() };
+        let mut h = Histogram::new(config.clone());
+        assert!(h.percentiles_monotonic());
+        for v in 0..=1000 {
+            h.append(v);
+        }
+        assert!(h.percentiles_monotonic());
+
+        // the median band of the range is above the mass of low values the wider band averages
+        let mut h = Histogram::new(config);
+        h.append(0);
+        h.append(1000);
+        for _ in 0..100 {
+            h.append(10);
+        }
+        h.append(500);
+        assert!(h.average_p(50).unwrap() > h.average_p(99).unwrap());
+        assert!(!h.percentiles_monotonic());
+    }
+
+    #[test]
+    fn test_recent_extremes() {
+        let mut h = Histogram::new(Config { extreme_log_size: 3, ..Default::default() });
+        for (t, v) in [(0, 5), (1, 3), (2, 8), (3, 8), (4, 20), (5, 1), (6, 21), (7, 40)] {
+            h.append_at(t, v);
+        }
+        assert_eq!(h.recent_extremes().copied().collect::<Vec<_>>(), vec![(4, 20), (6, 21), (7, 40)]);
+
+        let mut h = Histogram::new(Config::default());
+        h.append_at(0, 5);
+        assert_eq!(h.recent_extremes().count(), 0);
+        // of the encoded size
+        assert!(Config { extreme_log_size: u32::MAX as usize, ..Default::default() }.validate().is_ok());
+        #[cfg(target_pointer_width = "64")]
+        assert!(Config { extreme_log_size: u32::MAX as usize + 1, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_average_p_sig() {
+        assert_eq!(round_sig(48123, 2), 48000);
+        assert_eq!(round_sig(48523, 2), 49000);
+        assert_eq!(round_sig(99512, 3), 99500);
+        assert_eq!(round_sig(99951, 3), 100000);
+        assert_eq!(round_sig(48, 3), 48);
+        assert_eq!(round_sig(48123, 0), 48123);
+        assert_eq!(round_sig(0, 1), 0);
+        assert_eq!(round_sig(u64::MAX, 1), u64::MAX);
+
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        h.append(48123);
+        assert_eq!(h.average_p_sig(90, 2), Ok(48000));
+        assert!(h.average_p_sig(99, 2).is_err());
+    }
+
+    #[test]
+    fn test_add() {
+        let config = Config { percentiles: vec![9000], ..Default::default() };
+        let mut a = Histogram::new(config.clone());
+        let mut b = Histogram::new(config.clone());
+        a.append(10);
+        b.append(20);
+        b.append(30);
+        let mut total = a.clone() + b.clone();
+        assert_eq!(total.sample_count(), 3);
+        assert_eq!((total.range.min(), total.range.max()), (10, 30));
+        total += b.clone();
+        assert_eq!(total.sample_count(), 5);
+        assert_eq!(a.clone().try_add(&b).unwrap().sample_count(), 3);
+
+        let other = Histogram::new(Config::default());
+        assert_eq!(a.clone().try_add(&other).err(), Some(HistogramError::ConfigMismatch));
+        let panicked = std::panic::catch_unwind(|| a + other);
+        assert!(panicked.is_err());
+    }
+
+    #[test]
+    fn test_percentile_contributions() {
+        let mut h = seeded(Config { percentiles: vec![9000], span_sec: 1, live_time_sec: 100, ..Default::default() }, (0, 1000));
+        for t in 0..5u32 {
+            h.append_at(t * 2, 0);
+            h.append_at(t * 2, 1000);
+            h.append_at(t * 2, 500);
+        }
+        for _ in 0..20 {
+            h.append_at(4, 600);
+        }
+        let contributions = h.percentile_contributions(90).unwrap();
+        assert_eq!(contributions, vec![(8, 1), (6, 1), (4, 21), (2, 1), (0, 1)]);
+        assert_eq!(contributions.iter().map(|c| c.1).sum::<u64>(), h.sample_count_p(90).unwrap());
+        assert!(h.percentile_contributions(99).is_err());
+    }
+
+    #[test]
+    fn test_timeseries() {
+        let mut h = Histogram::new(Config { span_sec: 1, live_time_sec: 300, ..Default::default() });
+        assert!(h.timeseries(10).is_empty());
+        for t in 0..100u32 {
+            h.append_at(t * 2, t as u64);
+            h.append_at(t * 2, t as u64 + 2);
+        }
+        assert_eq!(h.buckets(), 100);
+        let series = h.timeseries(10);
+        assert_eq!(series.len(), 10);
+        assert!(series.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(series[0], (0, 5));
+        assert_eq!(series[9], (180, 95));
+        assert_eq!(h.timeseries(1000).len(), 100);
+    }
+
+    #[test]
+    fn test_memory_pressure() {
+        let config = Config { span_sec: 1, live_time_sec: 18, ..Default::default() };
+        let mut h = Histogram::new(config.clone());
+        assert_eq!(h.memory_pressure(), 0f64);
+        let mut last = 0f64;
+        for t in 0..5u32 {
+            h.append_at(t * 2, 1);
+            assert!(h.memory_pressure() > last);
+            last = h.memory_pressure();
+        }
+        assert_eq!(last, 0.25);
+        for t in 5..50u32 {
+            h.append_at(t * 2, 1);
+        }
+        assert!(h.memory_pressure() <= 1f64 && h.memory_pressure() >= 0.5);
+
+        let mut h = Histogram::with_ring_buffer(config.clone());
+        for t in 0..50u32 {
+            h.append_at(t, 1);
+        }
+        assert_eq!(h.memory_pressure(), h.buckets() as f64 / 20f64);
+        assert_eq!(Histogram::new(Config { live_time_sec: 0, ..config }).memory_pressure(), 0f64);
+    }
+
+    #[test]
+    fn test_lifetime_count_p() {
+        let mut h = seeded(Config { percentiles: vec![9000], span_sec: 1, live_time_sec: 5, ..Default::default() }, (0, 1000));
+        let mut last = 0;
+        for t in 0..20u32 {
+            h.append_at(t * 2, 0);
+            h.append_at(t * 2, 500);
+            h.append_at(t * 2, 1000);
+            let count = h.lifetime_count_p(90).unwrap();
+            assert!(count > last);
+            last = count;
+        }
+        assert_eq!(last, 20);
+        assert!(h.sample_count_p(90).unwrap() < 5);
+        assert!(h.lifetime_count_p(99).is_err());
+        h.reset_lifetime();
+        assert_eq!(h.lifetime_count_p(90), h.sample_count_p(90));
+    }
+
+    #[test]
+    fn test_recompute_range() {
+        let mut h = Histogram::new(Config::default());
+        h.append_at(0, 10);
+        h.append_at(0, 50);
+        h.append_at(5, 30);
+        h.range = Range { min_max: (0, 10000) };
+        h.range_lifetime = Range { min_max: (1, 20000) };
+        h.recompute_range();
+        assert_eq!(h.range.min_max, (10, 50));
+        assert_eq!(h.range_lifetime.min_max, (10, 50));
+
+        let mut h = Histogram::new(Config::default());
+        h.recompute_range();
+        assert_eq!(h.range, Range::default());
+    }
+
+    #[test]
+    fn test_min_range_width() {
+        let config = Config { percentiles: vec![5000], ..Default::default() };
+        let mut narrow = Histogram::new(config.clone());
+        let mut floored = Histogram::new(Config { min_range_width: 100, ..config.clone() });
+        for h in [&mut narrow, &mut floored] {
+            h.append(1000);
+            h.append(1010);
+            for v in 0..100 {
+                h.append(1000 + v % 11);
+            }
+        }
+        // the median band of 1000..1010 is 1003..1008 only
+        assert!(narrow.sample_count_p(50).unwrap() < 60);
+        assert_eq!(floored.sample_count_p(50).unwrap(), 102);
+        assert_eq!(floored.average_p(50), floored.mean().ok_or(HistogramError::InsufficientSamples));
+
+        let r = Range { min_max: (1000, 1010) };
+        assert_eq!(r.floored(Tail::Both, 100).min_max, (955, 1055));
+        assert_eq!(r.floored(Tail::Upper, 100).min_max, (910, 1010));
+        assert_eq!(r.floored(Tail::Lower, 100).min_max, (1000, 1100));
+        assert_eq!(r.floored(Tail::Both, 5).min_max, (1000, 1010));
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let baseline = Snapshot { count: 100, mean: 40, min: 1, max: 90, median: 45,
+            percentiles: vec![(9500, 48), (9990, 80)] };
+        let current = Snapshot { count: 120, mean: 42, min: 1, max: 99, median: 50,
+            percentiles: vec![(9000, 30), (9500, 62), (9990, 70)] };
+        assert_eq!(diff_report(&baseline, &current), "count: 100 -> 120 (+20%)\n\
+            mean: 40 -> 42 (+5%)\n\
+            p95: 48 -> 62 (+29%) regression\n\
+            p99.9: 80 -> 70 (-13%)");
+        let empty = Snapshot { count: 0, mean: 0, min: 0, max: 0, median: 0, percentiles: vec![] };
+        assert_eq!(diff_report(&empty, &current), "count: 0 -> 120 (new)\nmean: 0 -> 42 (new) regression");
+    }
+
+    #[test]
+    fn test_correlate() {
+        let start = Instant::now();
+        let mut load = Histogram::with_start(Config::default(), start);
+        let mut latency = Histogram::with_start(Config::default(), start);
+        let mut idle = Histogram::with_start(Config::default(), start);
+        for t in 0..10u32 {
+            let rps = [5, 9, 3, 7, 12, 4, 8, 10, 2, 6][t as usize];
+            load.append_at(t * 2, rps);
+            latency.append_at(t * 2, 20 + rps * 3);
+            latency.append_at(t * 2, 22 + rps * 3);
+            idle.append_at(t * 2, 100 - rps);
+        }
+        assert!((correlate(&load, &latency).unwrap() - 1f64).abs() < 1e-9);
+        assert!((correlate(&load, &idle).unwrap() + 1f64).abs() < 1e-9);
+        // the sums of some buckets past u64 are of the power
+        let mut big = Histogram::with_start(Config::default(), start);
+        for t in 0..10u32 {
+            let rps = [5, 9, 3, 7, 12, 4, 8, 10, 2, 6][t as usize];
+            big.append_at(t * 2, u64::MAX / 2 - 6_000_000 + rps * 1_000_000);
+            big.append_at(t * 2, u64::MAX / 2 - 6_000_000 + rps * 1_000_000);
+        }
+        assert!(big.buckets.iter().any(|b| b.scale[0].power > 0));
+        assert!(correlate(&load, &big).unwrap() > 0.99);
+        // a bucket begun at an odd second is not aligned
+        let mut late = Histogram::with_start(Config::default(), start);
+        late.append_at(1, 1);
+        late.append_at(2, 2);
+        assert_eq!(correlate(&load, &late), None);
+        let mut flat = Histogram::with_start(Config::default(), start);
+        for t in 0..5 {
+            flat.append_at(t * 2, 7);
+        }
+        assert_eq!(correlate(&load, &flat), None);
+    }
+
+    #[test]
+    fn test_exceeds() {
+        let mut h = seeded(Config { percentiles: vec![9000, 9900], ..Default::default() }, (0, 1000));
+        assert_eq!(h.exceeds(99, 10), Ok(false));
+        for v in 0..=1000 {
+            h.append(v);
+        }
+        let p99 = h.average_p(99).unwrap();
+        assert_eq!(h.exceeds(99, p99 - 1), Ok(true));
+        assert_eq!(h.exceeds(99, p99), Ok(false));
+        assert!(h.exceeds(95, 0).is_err());
+
+        let p90 = h.average_p(90).unwrap();
+        assert_eq!(h.exceeds_any(&[(90, p90), (99, p99)]), Ok(false));
+        assert_eq!(h.exceeds_any(&[(90, p90), (99, p99 - 1)]), Ok(true));
+        assert_eq!(h.exceeds_any(&[(90, p90 - 1), (99, u64::MAX)]), Ok(true));
+        assert_eq!(h.exceeds_any(&[]), Ok(false));
+        assert!(h.exceeds_any(&[(90, 0), (95, 0)]).is_err());
+    }
+
+    #[test]
+    fn test_lifetime_only() {
+        let mut h = Histogram::new(Config { span_sec: 1, live_time_sec: 10, lifetime_only: true, ..Default::default() });
+        assert_eq!(h.buckets(), 0);
+        for t in 0..100u32 {
+            h.append_at(t * 5, t as u64 + 1);
+        }
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(h.sample_count(), 100);
+        assert_eq!(h.mean(), Some(50));
+        assert_eq!((h.min_lt(), h.max()), (1, Some(100)));
+        assert_eq!(h.average_p(99), Err(HistogramError::NoWindow));
+        assert_eq!(h.config.find_hundredths(9900), Err(HistogramError::NoWindow));
+
+        // the merged buckets of any time are folded into the single one
+        let mut other = Histogram::new(h.config.as_ref().clone());
+        other.append_at(0, 1000);
+        let mut late = other.clone();
+        late.buckets[0].time = 10_000;
+        h.merge(&other).unwrap();
+        h.merge_bucket(late.buckets[0].clone()).unwrap();
+        h.fold_bucket_unchecked(late.buckets[0].clone());
+        let mut merged = Histogram::new(h.config.as_ref().clone());
+        merged.merge(&h).unwrap();
+        assert_eq!(h.buckets(), 1);
+        assert_eq!(merged.buckets(), 1);
+        assert_eq!(h.sample_count(), 103);
+        assert_eq!(merged.sample_count(), 103);
+        assert_eq!(merged.max(), Some(1000));
+        assert!(Config { percentiles: vec![9000], lifetime_only: true, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_bucket_variance() {
+        let mut h = Histogram::new(Config { span_sec: 5, live_time_sec: 60, ..Default::default() });
+        for v in [2, 4, 4, 4, 5, 5, 7, 9] {
+            h.append_at(0, v);
+        }
+        h.append_at(10, 100);
+        h.append_at(20, 10);
+        h.append_at(20, 20);
+        // mean 5, squared deviations 32 over 7
+        let b = &h.buckets[2];
+        assert!((b.variance().unwrap() - 32f64 / 7f64).abs() < 1e-9);
+        assert!((b.stddev().unwrap() - (32f64 / 7f64).sqrt()).abs() < 1e-9);
+        assert_eq!(h.buckets[1].variance(), None);
+        let stddevs = h.bucket_stddevs();
+        assert_eq!(stddevs.len(), 2);
+        assert_eq!(stddevs[0], (20, 50f64.sqrt()));
+        assert_eq!(stddevs[1].0, 0);
+        // a large value squared is beyond u64
+        let mut h = Histogram::new(Config::default());
+        h.append_at(0, u32::MAX as u64 * 4);
+        h.append_at(0, u32::MAX as u64 * 2);
+        assert!((h.buckets[0].stddev().unwrap() - u32::MAX as f64 * 2f64.sqrt()).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_skew() {
+        let config = Config { percentiles: vec![9500], ..Default::default() };
+        let mut centered = seeded(config.clone(), (0, 1000));
+        let mut skewed = seeded(Config { skew: 0.8, ..config }, (0, 1000));
+        for h in [&mut centered, &mut skewed] {
+            for v in 0..=1000 {
+                h.append(v);
+            }
+        }
+        // the 50 cut of the range, 25 off each side or 45 off the min and 5 off the max
+        let r = Range { min_max: (0, 1000) };
+        assert!(r.check_in(Tail::Both, 0.8, 9500, 45) && !r.check_in(Tail::Both, 0.8, 9500, 44));
+        assert!(r.check_in(Tail::Both, 0.8, 9500, 995) && !r.check_in(Tail::Both, 0.8, 9500, 996));
+        assert!(r.check_in(Tail::Both, 1.0, 9500, 1000) && !r.check_in(Tail::Both, 1.0, 9500, 49));
+        assert!(r.check_in(Tail::Both, -1.0, 9500, 0) && !r.check_in(Tail::Both, -1.0, 9500, 951));
+        assert_eq!(centered.sample_count_p(95), skewed.sample_count_p(95));
+        assert_eq!(centered.average_p(95), Ok(500));
+        assert_eq!(skewed.average_p(95), Ok(520));
+        assert!(Config { skew: 1.5, ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_aligned_buckets() {
+        let mut h = Histogram::new(Config { span_sec: 9, live_time_sec: 600, ..Default::default() });
+        h.epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_030);
+        // buckets begun at the epoch offsets 0, 10 .. 100, the epoch is 50 seconds into a minute
+        for t in 0..=10 {
+            h.append_at(t * 10, t as u64);
+        }
+        let rows = h.aligned_buckets(SystemTime::UNIX_EPOCH, Duration::from_secs(60));
+        let minute = |m: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(1_699_999_980 + m * 60);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows.iter().map(|r| r.0).collect::<Vec<_>>(), vec![minute(2), minute(1), minute(0)]);
+        assert_eq!(rows.iter().map(|r| r.1.count).collect::<Vec<_>>(), vec![4, 6, 1]);
+        assert_eq!((rows[1].1.min, rows[1].1.max, rows[1].1.mean), (1, 6, 3));
+        // of the epoch after the begin of the buckets
+        let anchor = h.epoch + Duration::from_secs(45);
+        let rows = h.aligned_buckets(anchor, Duration::from_secs(60));
+        assert_eq!(rows.iter().map(|r| (r.0, r.1.count)).collect::<Vec<_>>(),
+            vec![(anchor, 6), (anchor - Duration::from_secs(60), 5)]);
+        assert_eq!(h.aligned_buckets(anchor, Duration::ZERO).len(), 11);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut h = Histogram::new(Config { percentiles: vec![9000], span_sec: 1, live_time_sec: 10, ..Default::default() });
+        for t in 0..1000 {
+            h.buckets.push_back(Bucket::new(1000 - t));
+        }
+        h.append_at(1000, 1);
+        assert_eq!(h.buckets.len(), 11);
+        let grown = h.buckets.capacity();
+        assert!(grown >= 1000);
+        h.buckets[0].scale.reserve(100);
+        h.buckets[0].has_percentiles = false;
+        h.shrink_to_fit();
+        assert!(h.buckets.capacity() < grown / 10);
+        assert_eq!(h.buckets[0].scale.len(), 1);
+        assert!(h.buckets[0].scale.capacity() < 100);
+        assert_eq!(h.sample_count(), 1);
+
+        let mut ring = Histogram::with_ring_buffer(Config { span_sec: 1, live_time_sec: 10, ..Default::default() });
+        ring.append_at(0, 1);
+        let capacity = ring.buckets.capacity();
+        ring.shrink_to_fit();
+        assert_eq!(ring.buckets.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_count_above_below() {
+        let mut h = Histogram::new(Config::default());
+        for x in 0..101 {
+            h.append(x);
+        }
+        let above = h.count_above(50) as i64;
+        let below = h.count_below(50) as i64;
+        assert!((above - below).abs() <= 1);
+        assert_eq!(h.count_above(100), 0);
+        assert_eq!(h.count_below(0), 0);
+        assert_eq!(h.count_above(u64::MAX), 0);
+        assert_eq!(h.count_below(u64::MAX), h.sample_count());
+        assert_eq!(Histogram::new(Config::default()).count_above(0), 0);
+    }
+
+    #[test]
+    fn test_range_first_sample() {
+        let mut h = Histogram::new(Config::default());
+        h.append(5);
+        assert_eq!(h.range.min_max, (5, 5));
+        assert_eq!(h.buckets[0].range.min_max, (5, 5));
+        h.append(3);
+        h.append(7);
+        assert_eq!(h.range.min_max, (3, 7));
     }
 
 }
\ No newline at end of file
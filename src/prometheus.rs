@@ -0,0 +1,222 @@
+use crate::Histogram;
+
+/// native histogram buckets count to fit the range into
+const MAX_BUCKETS: i64 = 160;
+
+/// Native histogram with a single positive span
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Native {
+    /// bucket `i` covers `(base^(i-1), base^i]` with `base = 2^(2^-schema)`
+    schema: i32,
+    /// samples of 0
+    zero_count: u64,
+    /// index of the first positive bucket
+    offset: i32,
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Histogram {
+    /// native histogram `io.prometheus.client.MetricFamily` protobuf message, varint length delimited,
+    /// i.e. the `application/vnd.google.protobuf; encoding=delimited` exposition,
+    /// the schema is the finest to fit the global range into 160 buckets,
+    /// samples are spread uniformly within each bucket range
+    pub fn to_prometheus_native(&self, name: &str) -> Vec<u8> {
+        let n = self.native();
+        let mut span = Vec::new();
+        field_varint(&mut span, 1, zigzag(n.offset as i64));
+        field_varint(&mut span, 2, n.counts.len() as u64);
+        let mut deltas = Vec::new();
+        let mut prev = 0i64;
+        for c in &n.counts {
+            varint(&mut deltas, zigzag(*c as i64 - prev));
+            prev = *c as i64;
+        }
+        let mut h = Vec::new();
+        field_varint(&mut h, 1, n.zero_count + n.counts.iter().sum::<u64>());
+        field_double(&mut h, 2, n.sum);
+        field_varint(&mut h, 5, zigzag(n.schema as i64));
+        field_double(&mut h, 6, 0f64);
+        field_varint(&mut h, 7, n.zero_count);
+        if !n.counts.is_empty() {
+            field_bytes(&mut h, 12, &span);
+            field_bytes(&mut h, 13, &deltas);
+        }
+        let mut metric = Vec::new();
+        field_bytes(&mut metric, 7, &h);
+        let mut family = Vec::new();
+        field_bytes(&mut family, 1, name.as_bytes());
+        field_varint(&mut family, 3, 4 /* HISTOGRAM */);
+        field_bytes(&mut family, 4, &metric);
+        let mut out = Vec::with_capacity(family.len() + 10);
+        varint(&mut out, family.len() as u64);
+        out.extend_from_slice(&family);
+        out
+    }
+
+    pub(crate) fn native(&self) -> Native {
+        let total = self.scale_by_id(0);
+        let sum = total.sum as f64 + total.power as f64 * u64::MAX as f64;
+        let (min, max) = self.range.min_max;
+        if min > max || max == 0 {
+            return Native { schema: 0, zero_count: total.count, offset: 0, counts: vec![], sum };
+        }
+        let first = min.max(1);
+        let schema = (-4..=8).rev()
+            .find(|s| index(max, *s) - index(first, *s) < MAX_BUCKETS)
+            .unwrap_or(-4);
+        let offset = index(first, schema);
+        let len = (index(max, schema) - offset + 1) as usize;
+        let mut counts = vec![0f64; len];
+        let mut zeros = 0f64;
+        for b in &self.buckets {
+            let count = b.scale[0].count as f64;
+            let (lo, hi) = b.range.min_max;
+            if count == 0f64 {
+                continue;
+            }
+            let values = (hi - lo) as f64 + 1f64;
+            if lo == 0 {
+                zeros += count / values;
+            }
+            for (i, c) in counts.iter_mut().enumerate() {
+                let (l, u) = bounds(offset + i as i64, schema);
+                // integers within (l, u] and the bucket range
+                let from = (l.floor() as u64).saturating_add(1).max(lo).max(1);
+                let to = (u.floor() as u64).min(hi);
+                if to >= from {
+                    *c += count * (to - from + 1) as f64 / values;
+                }
+            }
+        }
+        let mut row = vec![zeros];
+        row.extend(counts);
+        let mut rounded = crate::heatmap::round_row(&row, total.count).into_iter();
+        let zero_count = rounded.next().unwrap_or(0);
+        Native { schema, zero_count, offset: offset as i32, counts: rounded.collect(), sum }
+    }
+}
+
+/// native bucket index of a positive value
+#[inline]
+fn index(value: u64, schema: i32) -> i64 {
+    ((value as f64).log2() * 2f64.powi(schema)).ceil() as i64
+}
+
+/// `(lower, upper]` of the native bucket
+#[inline]
+fn bounds(index: i64, schema: i32) -> (f64, f64) {
+    let f = 2f64.powi(-schema);
+    (2f64.powf((index - 1) as f64 * f), 2f64.powf(index as f64 * f))
+}
+
+#[inline]
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn field_varint(out: &mut Vec<u8>, field: u64, v: u64) {
+    varint(out, field << 3);
+    varint(out, v);
+}
+
+fn field_double(out: &mut Vec<u8>, field: u64, v: f64) {
+    varint(out, field << 3 | 1);
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn field_bytes(out: &mut Vec<u8>, field: u64, v: &[u8]) {
+    varint(out, field << 3 | 2);
+    varint(out, v.len() as u64);
+    out.extend_from_slice(v);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+        let mut v = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = data[*pos];
+            *pos += 1;
+            v |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return v;
+            }
+            shift += 7;
+        }
+    }
+
+    /// fields of a message as (number, wire type, varint or bytes)
+    fn fields(data: &[u8]) -> Vec<(u64, u64, u64, &[u8])> {
+        let mut pos = 0;
+        let mut r = vec![];
+        while pos < data.len() {
+            let key = read_varint(data, &mut pos);
+            match key & 7 {
+                0 => r.push((key >> 3, 0, read_varint(data, &mut pos), &data[0..0])),
+                1 => {
+                    r.push((key >> 3, 1, 0, &data[pos..pos + 8]));
+                    pos += 8;
+                }
+                2 => {
+                    let len = read_varint(data, &mut pos) as usize;
+                    r.push((key >> 3, 2, 0, &data[pos..pos + len]));
+                    pos += len;
+                }
+                t => panic!("wire type {}", t),
+            }
+        }
+        r
+    }
+
+    #[test]
+    fn test_native() {
+        let mut h = Histogram::new(Config::default());
+        for x in 0..1000 {
+            h.append_at(x % 10 * 2, x as u64 * 3);
+        }
+        for _ in 0..5 {
+            h.append_at(30, 0);
+        }
+        let n = h.native();
+        assert_eq!(n.zero_count + n.counts.iter().sum::<u64>(), 1005);
+        assert!(n.counts.len() as i64 <= MAX_BUCKETS);
+        assert_eq!(n.zero_count, 5);
+
+        let out = h.to_prometheus_native("latency");
+        let mut pos = 0;
+        let len = read_varint(&out, &mut pos) as usize;
+        assert_eq!(len, out.len() - pos);
+        let family = fields(&out[pos..]);
+        assert_eq!(family[0].3, b"latency");
+        assert_eq!((family[1].0, family[1].2), (3, 4));
+        let metric = fields(family[2].3);
+        let hist = fields(metric[0].3);
+        let get = |f: u64| hist.iter().find(|x| x.0 == f).unwrap();
+        assert_eq!(get(1).2, 1005);
+        let zero = get(7).2;
+        let deltas = get(13).3;
+        let (mut pos, mut count, mut total) = (0, 0i64, 0i64);
+        while pos < deltas.len() {
+            let d = read_varint(deltas, &mut pos);
+            count += (d >> 1) as i64 ^ -((d & 1) as i64);
+            total += count;
+        }
+        assert_eq!(total as u64 + zero, h.sample_count());
+
+        let empty = Histogram::new(Config::default()).native();
+        assert_eq!(empty.counts.len() as u64 + empty.zero_count, 0);
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use crate::Histogram;
+
+/// Front-end to append from many threads without waiting for the histogram.
+/// A record is a non-blocking send to the queue, the single owner of the histogram applies
+/// the queued values by [`Histogram::drain_recorder`].
+/// Reads are eventually consistent: values are missing until drained
+/// and are recorded at the time of the drain rather than of the record.
+#[derive(Debug)]
+pub struct Recorder {
+    tx: Sender<u64>,
+    rx: Mutex<Receiver<u64>>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        let (tx, rx) = channel();
+        Recorder { tx, rx: Mutex::new(rx) }
+    }
+
+    /// queue the value, never blocks
+    #[inline]
+    pub fn record(&self, value: u64) {
+        // the receiver lives as long as the sender
+        let _ = self.tx.send(value);
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
+
+impl Histogram {
+    /// append the values queued in the recorder, equal values are appended at once,
+    /// returns the count of the drained values
+    pub fn drain_recorder(&mut self, recorder: &Recorder) -> usize {
+        let mut values: Vec<u64> = {
+            let rx = recorder.rx.lock().unwrap_or_else(|e| e.into_inner());
+            rx.try_iter().collect()
+        };
+        values.sort_unstable();
+        let mut i = 0;
+        while i < values.len() {
+            let value = values[i];
+            let n = values[i..].iter().take(u32::MAX as usize).take_while(|v| **v == value).count();
+            self.append_n(value, n as u32);
+            i += n;
+        }
+        values.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::thread;
+
+    #[test]
+    fn test_drain_from_threads() {
+        let recorder = Recorder::new();
+        let mut h = Histogram::new(Config { percentiles: vec![9000], ..Default::default() });
+        thread::scope(|s| {
+            for t in 0..4u64 {
+                let recorder = &recorder;
+                s.spawn(move || {
+                    for v in 0..1000u64 {
+                        recorder.record(t * 1000 + v);
+                    }
+                });
+            }
+        });
+        assert_eq!(h.sample_count(), 0);
+        assert_eq!(h.drain_recorder(&recorder), 4000);
+        assert_eq!(h.sample_count(), 4000);
+        assert_eq!(h.mean(), Some(1999));
+        assert_eq!((h.range.min(), h.range.max()), (0, 3999));
+        assert_eq!(h.drain_recorder(&recorder), 0);
+
+        recorder.record(7);
+        recorder.record(7);
+        assert_eq!(h.drain_recorder(&recorder), 2);
+        assert_eq!(h.sample_count(), 4002);
+    }
+}
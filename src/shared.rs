@@ -0,0 +1,121 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock, RwLockReadGuard};
+use std::time::Instant;
+
+use crate::{Config, Histogram, Snapshot};
+
+/// Histogram to share between threads.
+/// Double buffered: the readers take the published copy of the two, an append records into the other one
+/// and publishes it, so a snapshot is computed over a consistent copy with no clone while the appends go on.
+/// An append waits only for a reader still on its copy since before the last publish,
+/// each sample is recorded into both copies, into the second one by the next append.
+#[derive(Debug)]
+pub struct SharedHistogram {
+    copies: [RwLock<Histogram>; 2],
+    /// index of the copy to read
+    published: AtomicUsize,
+    /// serializes the appends, `(start, time, value)` of the samples the other copy is behind by
+    pending: Mutex<Vec<(Instant, u32, u64)>>,
+}
+
+impl SharedHistogram {
+    pub fn new(config: Config) -> SharedHistogram {
+        SharedHistogram::from(Histogram::new(config))
+    }
+
+    #[inline]
+    fn read(&self) -> RwLockReadGuard<'_, Histogram> {
+        // a panic in between of append do not break the histogram invariants
+        self.copies[self.published.load(Ordering::Acquire)].read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn append(&self, value: u64) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let idx = 1 - self.published.load(Ordering::Acquire);
+        let mut h = self.copies[idx].write().unwrap_or_else(|e| e.into_inner());
+        for (start, time, value) in pending.drain(..) {
+            // of the same timeline, rolled as the other copy
+            if start > h.start {
+                let secs = (start - h.start).as_secs() as u32;
+                h.roll(secs);
+            }
+            h.record(time, value);
+        }
+        let time = h.tick();
+        h.record(time, value);
+        pending.push((h.start, time, value));
+        // published before the copy is released, so a reader never sees a newer copy than the published
+        self.published.store(idx, Ordering::Release);
+    }
+
+    /// consistent copy of the histogram
+    pub fn histogram(&self) -> Histogram {
+        self.read().clone()
+    }
+
+    /// consistent summary of the published copy
+    pub fn snapshot(&self) -> Snapshot {
+        self.read().snapshot()
+    }
+}
+
+impl From<Histogram> for SharedHistogram {
+    fn from(h: Histogram) -> Self {
+        SharedHistogram {
+            copies: [RwLock::new(h.clone()), RwLock::new(h)],
+            published: AtomicUsize::new(0),
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_snapshot_stress() {
+        let h = Arc::new(SharedHistogram::new(Config { percentiles: vec![9000], ..Default::default() }));
+        let done = Arc::new(AtomicBool::new(false));
+        let appenders: Vec<_> = (0..4).map(|_| {
+            let h = h.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut x = 0u64;
+                while !done.load(Ordering::Relaxed) {
+                    h.append(x % 1000);
+                    x += 1;
+                }
+            })
+        }).collect();
+        let mut count = 0;
+        for _ in 0..1000 {
+            let s = h.snapshot();
+            assert!(s.count >= count);
+            if s.count > 0 {
+                assert!(s.min <= s.mean && s.mean <= s.max && s.max < 1000);
+            }
+            count = s.count;
+        }
+        done.store(true, Ordering::Relaxed);
+        appenders.into_iter().for_each(|t| t.join().unwrap());
+        let s = h.snapshot();
+        assert!(s.count >= count);
+        assert_eq!(s.count, h.histogram().sample_count());
+
+        // an append goes on while a reader holds the published copy
+        let reader = h.read();
+        let appender = h.clone();
+        thread::spawn(move || appender.append(1)).join().unwrap();
+        assert_eq!(reader.sample_count(), s.count);
+        drop(reader);
+        assert_eq!(h.snapshot().count, s.count + 1);
+        h.append(2);
+        assert_eq!(h.histogram().sample_count(), s.count + 2);
+        // the other copy is behind by the last sample
+        assert_eq!(h.copies[h.published.load(Ordering::Acquire) ^ 1].read().unwrap().sample_count(), s.count + 1);
+    }
+}
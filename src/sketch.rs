@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use crate::Histogram;
+
+/// counts of the samples appended into a bucket by the bins of the relative width,
+/// a bin `i` holds the values of `(gamma^(i-1), gamma^i]`, so a bin is only allocated
+/// where the values fall and the hot regions get as many bins as they span
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Sketch {
+    pub(crate) bins: BTreeMap<i32, u64>,
+    /// of the zero value, which is out of the logarithmic bins
+    pub(crate) zero: u64,
+}
+
+/// ratio of the bin bounds, the middle of a bin is within `error` of any value of the bin
+#[inline]
+fn gamma(error: f64) -> f64 {
+    (1f64 + error) / (1f64 - error)
+}
+
+/// middle of the bin, within the relative error of its values
+#[inline]
+fn middle(bin: i32, gamma: f64) -> f64 {
+    2f64 * gamma.powi(bin) / (gamma + 1f64)
+}
+
+impl Sketch {
+    pub(crate) fn append_n(&mut self, value: u64, n: u64, gamma: f64) {
+        if value == 0 {
+            self.zero += n;
+        } else {
+            let bin = ((value as f64).ln() / gamma.ln()).ceil() as i32;
+            *self.bins.entry(bin).or_default() += n;
+        }
+    }
+
+    pub(crate) fn fold(&mut self, other: &Sketch) {
+        self.zero += other.zero;
+        for (bin, count) in &other.bins {
+            *self.bins.entry(*bin).or_default() += count;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.bins.clear();
+        self.zero = 0;
+    }
+
+    #[inline]
+    fn total(&self) -> u64 {
+        self.zero + self.bins.values().sum::<u64>()
+    }
+
+    /// re-binned with the middles multiplied by the factor
+    pub(crate) fn scaled(&self, factor: f64, error: f64) -> Sketch {
+        let gamma = gamma(error);
+        let mut r = Sketch { bins: BTreeMap::new(), zero: self.zero };
+        for (bin, count) in &self.bins {
+            r.append_n((middle(*bin, gamma) * factor).round() as u64, *count, gamma);
+        }
+        r
+    }
+}
+
+impl Histogram {
+    /// configured bound of the relative error, none if not set
+    #[inline]
+    pub(crate) fn relative_error(&self) -> Option<f64> {
+        match self.config.relative_error {
+            0 => None,
+            e => Some(e as f64 / 10000f64),
+        }
+    }
+
+    /// record into the sketch of the bucket by index
+    pub(crate) fn sketch_append(&mut self, idx: usize, value: u64, n: u32) {
+        if let Some(error) = self.relative_error() {
+            self.buckets[idx].sketch.append_n(value, n as u64, gamma(error));
+        }
+    }
+
+    /// value of the `p` (0..=1) share of the retained samples within the relative error of the exact,
+    /// the middle of the bin the rank falls in, clamped to the range, none if no sketch
+    /// or the sketches do not cover every retained sample, i.e. of the buckets loaded without them
+    pub(crate) fn sketch_quantile(&self, p: f64) -> Option<u64> {
+        let gamma = gamma(self.relative_error()?);
+        let mut all = Sketch::default();
+        for b in &self.buckets {
+            all.fold(&b.sketch);
+        }
+        let total = all.total();
+        if total == 0 || total != self.sample_count() {
+            return None;
+        }
+        let rank = ((p * total as f64).ceil() as u64).max(1);
+        if rank <= all.zero {
+            return Some(0);
+        }
+        let mut below = all.zero;
+        for (bin, count) in &all.bins {
+            below += count;
+            if below >= rank {
+                let (min, max) = self.range.min_max;
+                return Some((middle(*bin, gamma).round() as u64).clamp(min, max));
+            }
+        }
+        Some(self.range.min_max.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::assert_kept;
+    use crate::{Config, Histogram, HistogramError};
+
+    #[test]
+    fn test_relative_error_bound() {
+        let mut h = Histogram::new(Config { span_sec: 1, live_time_sec: 100, relative_error: 100, ..Default::default() });
+        // skewed of squares, the exact quantile of p is the value at the rank
+        let mut values: Vec<u64> = (1..=2000u64).map(|i| i * i).collect();
+        values.rotate_left(700);
+        for (i, v) in values.iter().enumerate() {
+            h.append_at(i as u32 / 200, *v);
+        }
+        values.sort_unstable();
+        for p in [0.01, 0.25, 0.5, 0.9, 0.95, 0.99, 0.999, 1.0] {
+            let exact = values[(p * values.len() as f64).ceil() as usize - 1] as f64;
+            let estimate = h.quantile(p).unwrap() as f64;
+            assert!((estimate - exact).abs() / exact <= 0.01, "p{} {} of {}", p, estimate, exact);
+            assert_eq!(h.quantile_error(p), Some(0.01));
+        }
+        assert_eq!(Histogram::new(Config { relative_error: 100, ..Default::default() }).quantile(0.5), Err(HistogramError::InsufficientSamples));
+
+        let mut band = Histogram::new(Config { span_sec: 1, live_time_sec: 100, ..Default::default() });
+        for (i, v) in values.iter().enumerate() {
+            band.append_at(i as u32 / 200, *v);
+        }
+        // of the sorted values a bucket is a slice of the range, the bounds are of the bucket the median is in
+        let (low, point, high) = band.quantile_with_bounds(0.5).unwrap();
+        assert_eq!(band.quantile_error(0.5), Some((point - low).max(high - point) as f64 / point as f64));
+        assert!(band.quantile_error(0.5).unwrap() > 0.1);
+    }
+
+    #[test]
+    fn test_relative_error_kept() {
+        let config = Config { span_sec: 1, live_time_sec: 100, relative_error: 100, ..Default::default() };
+        let values: Vec<u64> = (1..=1000u64).map(|i| i * i).collect();
+        let mut a = assert_kept(config.clone(), &values, |h, values| {
+            let exact = values[(0.9 * values.len() as f64).ceil() as usize - 1] as f64;
+            let estimate = h.quantile(0.9).unwrap() as f64;
+            assert!((estimate - exact).abs() / exact <= 0.01, "{} of {}", estimate, exact);
+            assert_eq!(h.quantile_error(0.9), Some(0.01));
+        });
+
+        // a bucket without the bins falls back to the bounds of the buckets
+        let mut plain = Histogram::new(Config { relative_error: 0, ..config });
+        plain.append_at(0, 5);
+        a.fold_bucket_unchecked(plain.buckets[0].clone());
+        let (low, point, high) = a.quantile_with_bounds(0.9).unwrap();
+        assert_eq!(a.quantile_error(0.9), Some((point - low).max(high - point) as f64 / point as f64));
+    }
+}
@@ -0,0 +1,171 @@
+use std::str::FromStr;
+
+use crate::{Config, HistogramError, PercentileEstimator, PercentileWindow, Rounding, Tail, TimeUnit};
+
+/// Parse a compact spec like `span=5,live=300,p=90,95,99.9`,
+/// `span` and `live` are required, optional `unit=ns|us|ms`, `top=N`, `min=N` samples for percentile
+/// `round=down|nearest`, `tail=N` multiple of the live time to keep the top band buckets
+/// `side=both|upper|lower` of the percentile bands, `max=N` valid value, `over=drop|clamp` the invalid
+/// `est=band|weighted|tdigest` percentile estimator, the `tdigest` of the feature, `warmup=N` seconds,
+/// `log=on|off` scale, `window=live|lifetime` of the percentile bands, `extremes=N` recent new max values to keep
+/// `width=N` min range width of the bands, `error=N` percent bound of the quantile relative error
+/// `skew=N` of -1..=1 shift of the bands toward the max and `lifetime=on|off` to record without the window.
+/// Values without a key continue the percentile list.
+impl FromStr for Config {
+    type Err = HistogramError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        let (mut span, mut live) = (false, false);
+        let mut key = "";
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let value = match token.split_once('=') {
+                Some((k, v)) => {
+                    key = k.trim();
+                    v.trim()
+                }
+                None if key == "p" => token,
+                None => return Err(invalid(format!("'{}' without a key", token))),
+            };
+            match key {
+                "span" => {
+                    config.span_sec = value.parse().map_err(|_| invalid(format!("bad span '{}'", value)))?;
+                    span = true;
+                }
+                "live" => {
+                    config.live_time_sec = value.parse().map_err(|_| invalid(format!("bad live '{}'", value)))?;
+                    live = true;
+                }
+                "p" => config.percentiles.push(parse_percentile(value)?),
+                "unit" => config.time_unit = match value {
+                    "ns" => TimeUnit::Nanos,
+                    "us" => TimeUnit::Micros,
+                    "ms" => TimeUnit::Millis,
+                    _ => return Err(invalid(format!("bad unit '{}'", value))),
+                },
+                "top" => config.top_n = Some(value.parse().map_err(|_| invalid(format!("bad top '{}'", value)))?),
+                "min" => config.min_samples_for_percentile = value.parse()
+                    .map_err(|_| invalid(format!("bad min '{}'", value)))?,
+                "round" => config.rounding = match value {
+                    "down" => Rounding::RoundDown,
+                    "nearest" => Rounding::RoundNearest,
+                    _ => return Err(invalid(format!("bad round '{}'", value))),
+                },
+                "tail" => config.tail_retention = Some(value.parse().map_err(|_| invalid(format!("bad tail '{}'", value)))?),
+                "side" => config.tail = match value {
+                    "both" => Tail::Both,
+                    "upper" => Tail::Upper,
+                    "lower" => Tail::Lower,
+                    _ => return Err(invalid(format!("bad side '{}'", value))),
+                },
+                "max" => config.max_valid_value = Some(value.parse().map_err(|_| invalid(format!("bad max '{}'", value)))?),
+                "over" => config.clamp_invalid = match value {
+                    "drop" => false,
+                    "clamp" => true,
+                    _ => return Err(invalid(format!("bad over '{}'", value))),
+                },
+                "est" => config.estimator = match value {
+                    "band" => PercentileEstimator::RangeBand,
+                    "weighted" => PercentileEstimator::WeightedBuckets,
+                    #[cfg(feature = "tdigest")]
+                    "tdigest" => PercentileEstimator::TDigest,
+                    _ => return Err(invalid(format!("bad est '{}'", value))),
+                },
+                "warmup" => config.warmup_sec = value.parse().map_err(|_| invalid(format!("bad warmup '{}'", value)))?,
+                "log" => config.log_scale = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid(format!("bad log '{}'", value))),
+                },
+                "window" => config.window = match value {
+                    "live" => PercentileWindow::Live,
+                    "lifetime" => PercentileWindow::Lifetime,
+                    _ => return Err(invalid(format!("bad window '{}'", value))),
+                },
+                "extremes" => config.extreme_log_size = value.parse()
+                    .map_err(|_| invalid(format!("bad extremes '{}'", value)))?,
+                "width" => config.min_range_width = value.parse()
+                    .map_err(|_| invalid(format!("bad width '{}'", value)))?,
+                "skew" => config.skew = value.parse().map_err(|_| invalid(format!("bad skew '{}'", value)))?,
+                "error" => config.relative_error = parse_percentile(value)?,
+                "lifetime" => config.lifetime_only = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(invalid(format!("bad lifetime '{}'", value))),
+                },
+                _ => return Err(invalid(format!("unknown key '{}'", key))),
+            }
+        }
+        if !span {
+            return Err(invalid("missing 'span'".to_string()));
+        }
+        if !live {
+            return Err(invalid("missing 'live'".to_string()));
+        }
+        config.clone().validate().map_err(HistogramError::InvalidConfig)?;
+        Ok(config)
+    }
+}
+
+#[inline]
+fn invalid(msg: String) -> HistogramError {
+    HistogramError::InvalidConfig(msg)
+}
+
+/// percent with up to two decimals to hundredths
+fn parse_percentile(value: &str) -> Result<u16, HistogramError> {
+    let bad = || invalid(format!("bad percentile '{}'", value));
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    if frac.len() > 2 || !frac.chars().all(|c| c.is_ascii_digit()) {
+        return Err(bad());
+    }
+    let int: u16 = int.parse().map_err(|_| bad())?;
+    let frac: u16 = format!("{:0<2}", frac).parse().map_err(|_| bad())?;
+    int.checked_mul(100).and_then(|p| p.checked_add(frac)).ok_or_else(bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let c: Config = "span=5,live=300,p=90,95,99".parse().unwrap();
+        assert_eq!(c, Config {
+            percentiles: vec![9000, 9500, 9900],
+            span_sec: 5,
+            live_time_sec: 300,
+            ..Default::default()
+        });
+        let c: Config = " live=10, span=1, p=99.9,99.99, unit=ms, top=5, min=7, round=nearest, tail=3, side=upper, max=5000, over=clamp, est=weighted, warmup=10, log=on, window=lifetime, extremes=4, width=50, skew=0.5, error=0.5".parse().unwrap();
+        assert_eq!(c.min_samples_for_percentile, 7);
+        assert_eq!(c.percentiles, vec![9990, 9999]);
+        assert_eq!(c.time_unit, TimeUnit::Millis);
+        assert_eq!(c.top_n, Some(5));
+        assert_eq!(c.rounding, Rounding::RoundNearest);
+        assert_eq!(c.tail_retention, Some(3));
+        assert_eq!(c.tail, Tail::Upper);
+        assert_eq!((c.max_valid_value, c.clamp_invalid), (Some(5000), true));
+        assert_eq!(c.estimator, PercentileEstimator::WeightedBuckets);
+        assert_eq!(c.warmup_sec, 10);
+        assert!(c.log_scale);
+        assert_eq!(c.window, PercentileWindow::Lifetime);
+        assert_eq!(c.extreme_log_size, 4);
+        assert_eq!(c.min_range_width, 50);
+        assert_eq!(c.skew, 0.5);
+        assert_eq!(c.relative_error, 50);
+        let c: Config = "span=1,live=120".parse().unwrap();
+        assert_eq!(c, Config::default());
+        let c: Config = "span=1,live=120,lifetime=on".parse().unwrap();
+        assert!(c.lifetime_only);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        for spec in ["span=5,live=300,p=100", "span=5,live=300,p=40", "span=5,live=300,p=9x",
+            "span=5,live=300,p=99.999", "live=300,p=90", "span=5", "span=0,live=300",
+            "span=5,live=300,q=1", "span=5,live=300,round=up", "span=5,live=300,tail=0", "span=5,live=300,side=up", "span=5,live=300,over=cap", "span=5,live=300,est=exact", "span=5,live=300,log=yes", "span=5,live=300,window=all", "span=5,live=300,extremes=-1", "span=5,live=300,width=x", "span=5,live=300,error=100", "span=5,live=300,skew=2", "span=5,live=300,skew=x", "span=5,live=300,lifetime=yes", "span=5,live=300,p=90,lifetime=on", "span=5,live=300,90", "span=500,live=300", "span=5,live=3"] {
+            assert!(matches!(spec.parse::<Config>(), Err(HistogramError::InvalidConfig(_))), "{}", spec);
+        }
+    }
+}
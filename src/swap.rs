@@ -0,0 +1,85 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::{Config, Histogram};
+
+/// Copy-on-write histogram for read heavy access, i.e. a metrics endpoint scraped often while
+/// appends are rare. An append clones the histogram, modifies and swaps the copy in,
+/// a read holds the lock only to clone the pointer, so readers never wait for a copy
+/// and always see a whole update. The reads are not lock-free: a read takes the read lock
+/// and briefly waits for the swap of a concurrent update. Each append costs a full clone, use [`crate::SharedHistogram`]
+/// for the frequent appends.
+#[derive(Debug)]
+pub struct SwapHistogram {
+    current: RwLock<Arc<Histogram>>,
+    /// serializes the writers so no update is lost between the clone and the swap
+    writer: Mutex<()>,
+}
+
+impl SwapHistogram {
+    pub fn new(config: Config) -> SwapHistogram {
+        SwapHistogram::from(Histogram::new(config))
+    }
+
+    /// consistent histogram as of the last update
+    pub fn load(&self) -> Arc<Histogram> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn append(&self, value: u64) {
+        self.update(|h| h.append(value));
+    }
+
+    /// apply the changes to a copy, readers see all of them at once
+    pub fn update<F: FnOnce(&mut Histogram)>(&self, f: F) {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut h = (*self.load()).clone();
+        f(&mut h);
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(h);
+    }
+}
+
+impl From<Histogram> for SwapHistogram {
+    fn from(h: Histogram) -> Self {
+        SwapHistogram { current: RwLock::new(Arc::new(h)), writer: Mutex::new(()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_swap_reads() {
+        let h = Arc::new(SwapHistogram::new(Config { percentiles: vec![9000], ..Default::default() }));
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4).map(|_| {
+            let h = h.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut count = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let s = h.load().snapshot();
+                    // appended in pairs of the same value
+                    assert_eq!(s.count % 2, 0);
+                    assert!(s.count >= count);
+                    if s.count > 0 {
+                        assert_eq!(s.min, 1);
+                    }
+                    count = s.count;
+                }
+            })
+        }).collect();
+        for x in 1..=200 {
+            h.update(|h| {
+                h.append(x);
+                h.append(1);
+            });
+        }
+        done.store(true, Ordering::Relaxed);
+        readers.into_iter().for_each(|t| t.join().unwrap());
+        h.append(7);
+        assert_eq!(h.load().sample_count(), 401);
+    }
+}
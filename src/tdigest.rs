@@ -0,0 +1,152 @@
+use crate::Histogram;
+
+/// bound of the centroids of a digest, the tail centroids are the finer
+const COMPRESSION: f64 = 50f64;
+
+/// t-digest of the samples appended into a bucket
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TDigest {
+    /// `(mean, weight)` ascending by the mean
+    pub(crate) centroids: Vec<(f64, u64)>,
+    /// appended since the last merge
+    pub(crate) buffer: Vec<(f64, u64)>,
+}
+
+impl TDigest {
+    pub(crate) fn append_n(&mut self, value: u64, n: u32) {
+        self.buffer.push((value as f64, n as u64));
+        if self.buffer.len() >= 4 * COMPRESSION as usize {
+            let mut all = std::mem::take(&mut self.centroids);
+            all.append(&mut self.buffer);
+            self.centroids = merge(all);
+        }
+    }
+
+    /// the centroids of both merged
+    pub(crate) fn fold(&mut self, other: &TDigest) {
+        if other.centroids.is_empty() && other.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.extend(other.centroids.iter().chain(&other.buffer));
+        self.centroids = merge(all);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.centroids.clear();
+        self.buffer.clear();
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.centroids.shrink_to_fit();
+        self.buffer.shrink_to_fit();
+    }
+
+    /// the means multiplied by the factor
+    pub(crate) fn scaled(&self, factor: f64) -> TDigest {
+        let scale = |c: &Vec<(f64, u64)>| c.iter().map(|(mean, weight)| (mean * factor, *weight)).collect();
+        TDigest { centroids: scale(&self.centroids), buffer: scale(&self.buffer) }
+    }
+}
+
+/// centroids sorted and merged while the weight is within `4 * total * q * (1 - q) / compression`
+/// of the cumulative share `q`, so the centroids near the tails keep few samples
+fn merge(mut all: Vec<(f64, u64)>) -> Vec<(f64, u64)> {
+    all.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total = all.iter().map(|c| c.1).sum::<u64>() as f64;
+    let mut merged: Vec<(f64, u64)> = Vec::with_capacity(all.len().min(4 * COMPRESSION as usize));
+    let mut below = 0u64;
+    for (mean, weight) in all {
+        if let Some(last) = merged.last_mut() {
+            let sum = last.1 + weight;
+            let q = (below as f64 + sum as f64 / 2f64) / total;
+            if sum as f64 <= 4f64 * total * q * (1f64 - q) / COMPRESSION {
+                last.0 += (mean - last.0) * weight as f64 / sum as f64;
+                last.1 = sum;
+                continue;
+            }
+            below += last.1;
+        }
+        merged.push((mean, weight));
+    }
+    merged
+}
+
+impl Histogram {
+    /// value below which the `p` (0..=1) share of the retained samples falls,
+    /// interpolated between the centroid means within the range, none if no digest
+    /// or the digests do not cover every retained sample, i.e. of the buckets loaded without them
+    pub(crate) fn digest_quantile(&self, p: f64) -> Option<u64> {
+        let all: Vec<(f64, u64)> = self.buckets.iter()
+            .flat_map(|b| b.digest.centroids.iter().chain(&b.digest.buffer))
+            .copied()
+            .collect();
+        if all.is_empty() || all.iter().map(|c| c.1).sum::<u64>() != self.sample_count() {
+            return None;
+        }
+        let (min, max) = (self.range.min_max.0 as f64, self.range.min_max.1 as f64);
+        let all = merge(all);
+        let total = all.iter().map(|c| c.1).sum::<u64>() as f64;
+        let rank = p * total;
+        // a centroid mean is at the middle of its weight
+        let (mut value, mut at, mut below) = (min, 0f64, 0f64);
+        for (mean, weight) in &all {
+            let center = below + *weight as f64 / 2f64;
+            if rank <= center {
+                let t = if center > at { (rank - at) / (center - at) } else { 1f64 };
+                return Some((value + (mean - value) * t).round().clamp(min, max) as u64);
+            }
+            (value, at) = (*mean, center);
+            below += *weight as f64;
+        }
+        let t = if total > at { (rank - at) / (total - at) } else { 1f64 };
+        Some((value + (max - value) * t).round().clamp(min, max) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::assert_kept;
+    use crate::{Config, Histogram, HistogramError, PercentileEstimator};
+
+    #[test]
+    fn test_tdigest_accuracy() {
+        let config = Config { percentiles: vec![9900], span_sec: 1, live_time_sec: 100, ..Default::default() };
+        let mut band = Histogram::new(config.clone());
+        let mut digest = Histogram::new(Config { estimator: PercentileEstimator::TDigest, ..config });
+        // skewed of cubes, the exact p99 is 990^3
+        let mut values: Vec<u64> = (0..1000u64).map(|i| i * i * i).collect();
+        values.reverse();
+        values.rotate_left(300);
+        for (i, v) in values.iter().enumerate() {
+            band.append_at(i as u32 / 100, *v);
+            digest.append_at(i as u32 / 100, *v);
+        }
+        let exact = 990u64.pow(3) as f64;
+        let error = |v: u64| (v as f64 - exact).abs() / exact;
+        assert!(error(digest.average_p(99).unwrap()) < 0.01, "{}", digest.average_p(99).unwrap());
+        assert!(error(band.average_p(99).unwrap()) > 0.2);
+        assert_eq!(digest.quantile(0.99), digest.average_p(99));
+        assert!((digest.quantile(0.5).unwrap() as f64 - 500f64.powi(3)).abs() / 500f64.powi(3) < 0.05);
+        assert_eq!(Histogram::new(Config { estimator: PercentileEstimator::TDigest, ..Default::default() }).quantile(0.5), Err(HistogramError::InsufficientSamples));
+    }
+
+    #[test]
+    fn test_tdigest_kept() {
+        let config = Config { span_sec: 1, live_time_sec: 100, estimator: PercentileEstimator::TDigest, ..Default::default() };
+        let values: Vec<u64> = (0..1000u64).map(|i| i * i * i).collect();
+        let mut a = assert_kept(config.clone(), &values, |h, values| {
+            let exact = values[(0.5 * values.len() as f64) as usize] as f64;
+            let estimate = h.quantile(0.5).unwrap() as f64;
+            assert!((estimate - exact).abs() / exact < 0.05, "{} of {}", estimate, exact);
+        });
+
+        // a bucket without the digest leaves the digests out
+        let mut plain = Histogram::new(Config { estimator: PercentileEstimator::RangeBand, ..config });
+        plain.append_at(0, 5);
+        a.fold_bucket_unchecked(plain.buckets[0].clone());
+        assert_eq!(a.digest_quantile(0.5), None);
+        assert!(a.quantile(0.5).is_ok());
+    }
+}
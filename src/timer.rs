@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+use crate::Histogram;
+
+/// Guard appending the time elapsed since its start on drop, in the configured time unit.
+/// It borrows the histogram mutably for the scope, so nothing else appends meanwhile,
+/// time a scope on another thread by a [`crate::SharedHistogram`] or a [`crate::Recorder`] instead.
+#[derive(Debug)]
+pub struct Timer<'a> {
+    histogram: &'a mut Histogram,
+    start: Instant,
+}
+
+impl Histogram {
+    /// time the scope of the returned guard
+    pub fn start_timer(&mut self) -> Timer<'_> {
+        Timer { histogram: self, start: Instant::now() }
+    }
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        self.histogram.append_duration(self.start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::{Config, Histogram, TimeUnit};
+
+    #[test]
+    fn test_timer() {
+        let mut h = Histogram::new(Config { time_unit: TimeUnit::Micros, ..Default::default() });
+        {
+            let _timer = h.start_timer();
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(h.sample_count(), 1);
+        assert!(h.mean().unwrap() >= 2000);
+        drop(h.start_timer());
+        assert_eq!(h.sample_count(), 2);
+    }
+}
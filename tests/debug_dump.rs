@@ -0,0 +1,20 @@
+#![cfg(feature = "testing")]
+
+use shim::{Config, Histogram};
+
+#[test]
+fn test_debug_dump() {
+    let mut h = Histogram::new(Config::default());
+    h.append_at(0, 10);
+    h.append_at(0, 20);
+    h.append_at(5, 1000);
+    h.append_at(200, 7);
+    let view = h.debug_dump();
+    // the first buckets are evicted by live time
+    assert_eq!(view.buckets.len(), 1);
+    assert_eq!(view.buckets[0].time, 200);
+    assert_eq!(view.buckets[0].scale[0].count, 1);
+    assert_eq!((view.range.min(), view.range.max()), (7, 7));
+    assert_eq!((view.range_lifetime.min(), view.range_lifetime.max()), (7, 1000));
+    assert_eq!(view.config, Config::default());
+}